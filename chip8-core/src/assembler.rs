@@ -0,0 +1,1168 @@
+use crate::error::AssembleError;
+use crate::instruction::Instruction::{self, *};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Where programs are loaded, and so the address of their first byte.
+const ORIGIN: usize = 0x200;
+
+/// Macros expanded in one program before giving up on it, to catch macros that expand themselves.
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// A word of the source, and where it starts.
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,   // Counted from 1.
+    column: usize, // Counted from 1, in characters.
+}
+
+impl Token {
+    fn error<S: Into<String>>(&self, message: S) -> AssembleError {
+        AssembleError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+/// A `:macro`, expanded wherever its name is used as a statement.
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// An address to fill in once the label it refers to is defined.
+struct Fixup {
+    offset: usize, // Of the opcode (or the word after `i := long`) in the program.
+    label: Token,
+    long: bool, // A 16-bit address rather than the low 12 bits of an opcode.
+}
+
+/// A control structure that hasn't been closed yet.
+enum Block {
+    If {
+        jump: usize, // The jump over the body of `if ... begin`, taken when the condition fails.
+        token: Token,
+    },
+    Else {
+        jump: usize, // The jump over the body of `else`.
+        token: Token,
+    },
+    Loop {
+        start: usize,
+        whiles: Vec<usize>, // The jumps out of the loop, one per `while`.
+        token: Token,
+    },
+}
+
+/// A comparison in `if` and `while`, eg. `v0 == 3` or `v1 -key`.
+#[derive(Clone, Copy)]
+struct Condition {
+    x: usize,
+    comparison: Comparison,
+    operand: Operand,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    Key,    // `key`: The key in VX is pressed.
+    NotKey, // `-key`: The key in VX isn't pressed.
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(usize),
+    Value(usize), // A byte.
+    None,         // `key` and `-key` compare with nothing.
+}
+
+/// Assemble Octo source into a program to load at 0x200.
+///
+/// Statements are separated by whitespace, and `#` comments out the rest of a line. Supported are:
+/// - `: name` labels, `:const name value`, `:alias name vx` and `:macro name args { body }`.
+/// - Instructions such as `clear`, `return`, `jump label`, `sprite vx vy n`, `i := label`,
+///   `i := hex vx`, `vx := random 0xFF`, `vx += vy`, `save vx`, `bcd vx` and a bare label name to
+///   call it, along with the SUPER-CHIP and XO-CHIP ones (`hires`, `scroll-down n`,
+///   `i := long label`, `plane n`, `save vx - vy`...).
+/// - `if condition then statement`, `if condition begin ... else ... end` and
+///   `loop ... while condition ... again`. Conditions are `vx == n`, `!=`, `<`, `>`, `<=`, `>=`
+///   (against a byte or register), `vx key` and `vx -key`. `<` and the like use VF.
+/// - Numbers in decimal, `0x` hex or `0b` binary, which on their own are bytes of data.
+///
+/// Execution starts at `: main` if there is one, with a jump to it if it isn't first.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    assemble_with_source_map(source).map(|(program, _)| program)
+}
+
+/// Assemble Octo source, along with the line each part of the program came from. See `assemble`.
+pub fn assemble_with_source_map(source: &str) -> Result<(Vec<u8>, SourceMap), AssembleError> {
+    let mut assembler = Assembler::new(tokenize(source));
+    assembler.run()?;
+    assembler.source_map.end = assembler.address();
+    Ok((assembler.program, assembler.source_map))
+}
+
+/// The source lines of an assembled program, for debugging it. Code from a macro is on the lines
+/// of the macro's body.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceMap {
+    lines: BTreeMap<usize, usize>, // The address of each statement's first byte, and its line.
+    end: usize,                    // The address after the program.
+}
+
+impl SourceMap {
+    /// The line (counted from 1) of the statement that the byte at `address` is part of.
+    pub fn line(&self, address: usize) -> Option<usize> {
+        if address >= self.end {
+            return None;
+        }
+        self.lines
+            .range(..=address)
+            .next_back()
+            .map(|(_, &line)| line)
+    }
+
+    /// The address of the first statement on a line, or if it has none (being blank or a comment)
+    /// the first on the lines after it.
+    pub fn address(&self, line: usize) -> Option<usize> {
+        self.lines
+            .iter()
+            .filter(|&(_, &l)| l >= line)
+            .min_by_key(|&(&address, &l)| (l, address))
+            .map(|(&address, _)| address)
+    }
+}
+
+/// Split the source into words, without comments.
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (number, line) in source.lines().enumerate() {
+        let mut text = String::new();
+        let mut start = 0;
+        for (column, c) in line.chars().chain(Some(' ')).enumerate() {
+            if c == '#' || c.is_whitespace() {
+                if !text.is_empty() {
+                    tokens.push_back(Token {
+                        text: std::mem::take(&mut text),
+                        line: number + 1,
+                        column: start + 1,
+                    });
+                }
+                if c == '#' {
+                    break;
+                }
+            } else {
+                if text.is_empty() {
+                    start = column;
+                }
+                text.push(c);
+            }
+        }
+    }
+    tokens
+}
+
+/// A number in decimal, `0x` hex or `0b` binary, possibly negative.
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// The number of a register named v0 - vf.
+fn parse_register(text: &str) -> Option<usize> {
+    match text.strip_prefix('v').or_else(|| text.strip_prefix('V')) {
+        Some(digit) if digit.len() == 1 => usize::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
+
+/// Can the word be used as the name of a label, constant, alias or macro?
+fn is_name(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_alphabetic() || c == '_') && parse_register(text).is_none()
+}
+
+/// `address`, or an error at `token` if it's past the 12 bits a jump or call takes. `what` names
+/// the place being jumped to.
+fn twelve_bit(token: &Token, what: &str, address: usize) -> Result<usize, AssembleError> {
+    if address > 0xFFF {
+        return Err(token.error(format!(
+            "{} is at {:#X}, beyond the 12-bit addresses this instruction takes",
+            what, address
+        )));
+    }
+    Ok(address)
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    program: Vec<u8>,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    expansions: usize,
+    main: Option<Token>, // The `main` label, until the program starts.
+    source_map: SourceMap,
+}
+
+impl Assembler {
+    fn new(tokens: VecDeque<Token>) -> Self {
+        Assembler {
+            tokens,
+            program: Vec::new(),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            expansions: 0,
+            main: None,
+            source_map: SourceMap::default(),
+        }
+    }
+
+    /// Assemble every statement, then fill in the addresses of labels used before they were defined.
+    fn run(&mut self) -> Result<(), AssembleError> {
+        self.main = self.find_main();
+
+        while let Some(token) = self.tokens.pop_front() {
+            self.start_program(&token)?;
+
+            let (start, line) = (self.address(), token.line);
+            self.statement(token)?;
+            if self.address() > start {
+                self.source_map.lines.insert(start, line);
+            }
+        }
+
+        if let Some(block) = self.blocks.last() {
+            return Err(match block {
+                Block::If { token, .. } | Block::Else { token, .. } => {
+                    token.error(format!("'{}' has no matching 'end'", token.text))
+                }
+                Block::Loop { token, .. } => token.error("'loop' has no matching 'again'"),
+            });
+        }
+
+        for fixup in &self.fixups {
+            let address = *self.labels.get(&fixup.label.text).ok_or_else(|| {
+                fixup
+                    .label
+                    .error(format!("Unknown name '{}'", fixup.label.text))
+            })?;
+            let offset = fixup.offset;
+            if fixup.long {
+                self.program[offset] = (address >> 8) as u8;
+                self.program[offset + 1] = address as u8;
+            } else {
+                let what = format!("Label '{}'", fixup.label.text);
+                let address = twelve_bit(&fixup.label, &what, address)?;
+                self.program[offset] |= (address >> 8) as u8;
+                self.program[offset + 1] = address as u8;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The name in the `main` label's definition, if there is one.
+    fn find_main(&self) -> Option<Token> {
+        let tokens: Vec<&Token> = self.tokens.iter().collect();
+        tokens
+            .windows(2)
+            .find(|pair| pair[0].text == ":" && pair[1].text == "main")
+            .map(|pair| pair[1].clone())
+    }
+
+    /// Before the program's first byte or label, jump to `main` unless that's what comes first.
+    /// Definitions that emit nothing may come before it.
+    fn start_program(&mut self, token: &Token) -> Result<(), AssembleError> {
+        let defines_nothing = matches!(token.text.as_str(), ":const" | ":alias" | ":macro")
+            || self.macros.contains_key(&token.text);
+        if defines_nothing {
+            return Ok(());
+        }
+        if let Some(main) = self.main.take() {
+            if !(token.text == ":" && self.peek_is("main")) {
+                self.emit_jump(JUMP, &main)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn next(&mut self, after: &Token) -> Result<Token, AssembleError> {
+        self.tokens
+            .pop_front()
+            .ok_or_else(|| after.error(format!("Unexpected end of file after '{}'", after.text)))
+    }
+
+    /// Take the next word, which must be `word`.
+    fn expect(&mut self, after: &Token, word: &str) -> Result<Token, AssembleError> {
+        let token = self.next(after)?;
+        if token.text == word {
+            Ok(token)
+        } else {
+            Err(token.error(format!("Expected '{}', found '{}'", word, token.text)))
+        }
+    }
+
+    fn peek_is(&self, word: &str) -> bool {
+        self.tokens.front().is_some_and(|t| t.text == word)
+    }
+
+    fn address(&self) -> usize {
+        ORIGIN + self.program.len()
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        let opcode = instruction.encode();
+        self.program.push((opcode >> 8) as u8);
+        self.program.push(opcode as u8);
+    }
+
+    /// Emit a jump, call or the like to the address of `target`, which may be defined later.
+    fn emit_jump(
+        &mut self,
+        instruction: fn(usize) -> Instruction,
+        target: &Token,
+    ) -> Result<(), AssembleError> {
+        let address = match self.known_value(target)? {
+            Some(address) if (0..=0xFFF).contains(&address) => address as usize,
+            Some(address) => {
+                return Err(target.error(format!("Address {:#X} is beyond 0xFFF", address)))
+            }
+            None => {
+                self.fixups.push(Fixup {
+                    offset: self.program.len(),
+                    label: target.clone(),
+                    long: false,
+                });
+                0
+            }
+        };
+        self.emit(instruction(address));
+        Ok(())
+    }
+
+    /// The value of a number, constant or label already defined. Other names may be labels
+    /// defined later.
+    fn known_value(&self, token: &Token) -> Result<Option<i64>, AssembleError> {
+        if let Some(value) = parse_number(&token.text) {
+            Ok(Some(value))
+        } else if let Some(&value) = self.constants.get(&token.text) {
+            Ok(Some(value))
+        } else if let Some(&address) = self.labels.get(&token.text) {
+            Ok(Some(address as i64))
+        } else if is_name(&token.text) {
+            Ok(None)
+        } else {
+            Err(token.error(format!("Expected a number or name, found '{}'", token.text)))
+        }
+    }
+
+    /// The value of the next word, which must be known now and within `min..=max`.
+    fn value(&mut self, after: &Token, min: i64, max: i64) -> Result<usize, AssembleError> {
+        let token = self.next(after)?;
+        self.value_of(&token, min, max)
+    }
+
+    fn value_of(&self, token: &Token, min: i64, max: i64) -> Result<usize, AssembleError> {
+        match self.known_value(token)? {
+            Some(value) if value >= min && value <= max => Ok((value & 0xFFFF) as usize),
+            Some(value) => Err(token.error(format!(
+                "{} is out of range, expected {} to {}",
+                value, min, max
+            ))),
+            None => Err(token.error(format!("Unknown name '{}'", token.text))),
+        }
+    }
+
+    fn byte(&mut self, after: &Token) -> Result<usize, AssembleError> {
+        self.value(after, -128, 255).map(|v| v & 0xFF)
+    }
+
+    fn byte_of(&self, token: &Token) -> Result<usize, AssembleError> {
+        self.value_of(token, -128, 255).map(|v| v & 0xFF)
+    }
+
+    fn nibble(&mut self, after: &Token) -> Result<usize, AssembleError> {
+        self.value(after, 0, 15)
+    }
+
+    fn register_of(&self, token: &Token) -> Option<usize> {
+        parse_register(&token.text).or_else(|| self.aliases.get(&token.text).copied())
+    }
+
+    fn register(&mut self, after: &Token) -> Result<usize, AssembleError> {
+        let token = self.next(after)?;
+        self.register_of(&token)
+            .ok_or_else(|| token.error(format!("Expected a register, found '{}'", token.text)))
+    }
+
+    /// A name for a new label, constant, alias or macro.
+    fn new_name(&mut self, after: &Token) -> Result<Token, AssembleError> {
+        let token = self.next(after)?;
+        if !is_name(&token.text) {
+            Err(token.error(format!("'{}' can't be used as a name", token.text)))
+        } else if self.labels.contains_key(&token.text)
+            || self.constants.contains_key(&token.text)
+            || self.aliases.contains_key(&token.text)
+            || self.macros.contains_key(&token.text)
+        {
+            Err(token.error(format!("'{}' is already defined", token.text)))
+        } else {
+            Ok(token)
+        }
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AssembleError> {
+        if let Some(x) = self.register_of(&token) {
+            return self.register_statement(x, &token);
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.new_name(&token)?;
+                let address = self.address();
+                self.labels.insert(name.text, address);
+            }
+            ":const" => {
+                let name = self.new_name(&token)?;
+                let value_token = self.next(&name)?;
+                let value = self.known_value(&value_token)?.ok_or_else(|| {
+                    value_token.error(format!("Unknown name '{}'", value_token.text))
+                })?;
+                self.constants.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.new_name(&token)?;
+                let x = self.register(&name)?;
+                self.aliases.insert(name.text, x);
+            }
+            ":macro" => self.define_macro(&token)?,
+            ":byte" => {
+                let value = self.byte(&token)?;
+                self.program.push(value as u8);
+            }
+            ":call" => {
+                let target = self.next(&token)?;
+                self.emit_jump(CALL, &target)?;
+            }
+            "clear" => self.emit(CLR),
+            "return" | ";" => self.emit(RTS),
+            "scroll-down" => {
+                let n = self.nibble(&token)?;
+                self.emit(SCD(n));
+            }
+            "scroll-up" => {
+                let n = self.nibble(&token)?;
+                self.emit(SCU(n));
+            }
+            "scroll-right" => self.emit(SCR),
+            "scroll-left" => self.emit(SCL),
+            "exit" => self.emit(EXIT),
+            "lores" => self.emit(LOW),
+            "hires" => self.emit(HIGH),
+            "jump" | "jump0" | "native" => {
+                let target = self.next(&token)?;
+                let instruction = match token.text.as_str() {
+                    "jump" => JUMP,
+                    "jump0" => JUMPI,
+                    _ => SYS,
+                };
+                self.emit_jump(instruction, &target)?;
+            }
+            "sprite" => {
+                let x = self.register(&token)?;
+                let y = self.register(&token)?;
+                let n = self.nibble(&token)?;
+                self.emit(DRAW(x, y, n));
+            }
+            "bcd" => {
+                let x = self.register(&token)?;
+                self.emit(BCD(x));
+            }
+            "save" | "load" => {
+                let x = self.register(&token)?;
+                if self.peek_is("-") {
+                    let dash = self.next(&token)?;
+                    let y = self.register(&dash)?;
+                    self.emit(if token.text == "save" {
+                        SAVE(x, y)
+                    } else {
+                        LOADR(x, y)
+                    });
+                } else {
+                    self.emit(if token.text == "save" {
+                        STOR(x)
+                    } else {
+                        READ(x)
+                    });
+                }
+            }
+            "saveflags" => {
+                let x = self.register(&token)?;
+                self.emit(SRPL(x));
+            }
+            "loadflags" => {
+                let x = self.register(&token)?;
+                self.emit(LRPL(x));
+            }
+            "plane" => {
+                let n = self.nibble(&token)?;
+                self.emit(PLANE(n));
+            }
+            "audio" => self.emit(AUDIO),
+            "delay" | "buzzer" | "pitch" => {
+                let assign = self.expect(&token, ":=")?;
+                let x = self.register(&assign)?;
+                self.emit(match token.text.as_str() {
+                    "delay" => LOADD(x),
+                    "buzzer" => LOADS(x),
+                    _ => PITCH(x),
+                });
+            }
+            "i" => self.index_statement(&token)?,
+            "if" => self.if_statement(&token)?,
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) => {
+                    let end = self.program.len();
+                    self.emit(JUMP(0));
+                    self.patch_jump(jump, &token)?;
+                    self.blocks.push(Block::Else { jump: end, token });
+                }
+                _ => return Err(token.error("'else' without 'if ... begin'")),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) | Some(Block::Else { jump, .. }) => {
+                    self.patch_jump(jump, &token)?
+                }
+                _ => return Err(token.error("'end' without 'if ... begin'")),
+            },
+            "loop" => {
+                let start = self.address();
+                self.blocks.push(Block::Loop {
+                    start,
+                    whiles: Vec::new(),
+                    token,
+                });
+            }
+            "while" => {
+                let condition = self.condition(&token)?;
+                self.emit_skip_unless(condition.negated());
+                let jump = self.program.len();
+                self.emit(JUMP(0));
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|b| matches!(b, Block::Loop { .. }))
+                {
+                    Some(Block::Loop { whiles, .. }) => whiles.push(jump),
+                    _ => return Err(token.error("'while' outside of 'loop'")),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop {
+                    start,
+                    whiles,
+                    token: loop_token,
+                }) => {
+                    self.emit(JUMP(twelve_bit(&loop_token, "'loop'", start)?));
+                    for jump in whiles {
+                        self.patch_jump(jump, &token)?;
+                    }
+                }
+                _ => return Err(token.error("'again' without 'loop'")),
+            },
+            text if parse_number(text).is_some() => {
+                let value = self.byte_of(&token)?;
+                self.program.push(value as u8);
+            }
+            text if self.macros.contains_key(text) => self.expand_macro(&token)?,
+            text if self.constants.contains_key(text) => {
+                return Err(token.error(format!("Constant '{}' can't be used as a statement", text)))
+            }
+            text if is_name(text) => self.emit_jump(CALL, &token)?,
+            text => return Err(token.error(format!("Unknown statement '{}'", text))),
+        }
+        Ok(())
+    }
+
+    /// `vx := ...`, `vx += ...` and the other operations on a register.
+    fn register_statement(&mut self, x: usize, token: &Token) -> Result<(), AssembleError> {
+        let op = self.next(token)?;
+        let operand = self.next(&op)?;
+        let y = self.register_of(&operand);
+
+        let instruction = match (op.text.as_str(), operand.text.as_str(), y) {
+            (":=", "random", _) => RAND(x, self.byte(&operand)?),
+            (":=", "delay", _) => MOVED(x),
+            (":=", "key", _) => KEYD(x),
+            (":=", _, Some(y)) => MOVE(x, y),
+            (":=", _, None) => LOAD(x, self.byte_of(&operand)?),
+            ("+=", _, Some(y)) => ADDR(x, y),
+            ("+=", _, None) => ADD(x, self.byte_of(&operand)?),
+            ("-=", _, Some(y)) => SUB(x, y),
+            ("-=", _, None) => ADD(x, self.byte_of(&operand)?.wrapping_neg() & 0xFF),
+            ("=-", _, Some(y)) => SUBN(x, y),
+            ("|=", _, Some(y)) => OR(x, y),
+            ("&=", _, Some(y)) => AND(x, y),
+            ("^=", _, Some(y)) => XOR(x, y),
+            (">>=", _, Some(y)) => SHR(x, y),
+            ("<<=", _, Some(y)) => SHL(x, y),
+            ("=-", _, None)
+            | ("|=", _, None)
+            | ("&=", _, None)
+            | ("^=", _, None)
+            | (">>=", _, None)
+            | ("<<=", _, None) => {
+                return Err(operand.error(format!(
+                    "'{}' needs a register, found '{}'",
+                    op.text, operand.text
+                )))
+            }
+            _ => {
+                return Err(op.error(format!(
+                    "Expected an operator such as ':=' or '+=', found '{}'",
+                    op.text
+                )))
+            }
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    /// `i := address`, `i := hex vx`, `i := bighex vx`, `i := long address` and `i += vx`.
+    fn index_statement(&mut self, token: &Token) -> Result<(), AssembleError> {
+        let op = self.next(token)?;
+        match op.text.as_str() {
+            ":=" => {
+                let operand = self.next(&op)?;
+                match operand.text.as_str() {
+                    "hex" => {
+                        let x = self.register(&operand)?;
+                        self.emit(LDSPR(x));
+                    }
+                    "bighex" => {
+                        let x = self.register(&operand)?;
+                        self.emit(LDHSPR(x));
+                    }
+                    "long" => {
+                        let target = self.next(&operand)?;
+                        let address = match self.known_value(&target)? {
+                            Some(address) if (0..=0xFFFF).contains(&address) => address as usize,
+                            Some(address) => {
+                                return Err(target
+                                    .error(format!("Address {:#X} is beyond 0xFFFF", address)))
+                            }
+                            None => {
+                                self.fixups.push(Fixup {
+                                    offset: self.program.len() + 2,
+                                    label: target,
+                                    long: true,
+                                });
+                                0
+                            }
+                        };
+                        self.emit(LOADIL);
+                        self.program.push((address >> 8) as u8);
+                        self.program.push(address as u8);
+                    }
+                    _ => self.emit_jump(LOADI, &operand)?,
+                }
+            }
+            "+=" => {
+                let x = self.register(&op)?;
+                self.emit(ADDI(x));
+            }
+            _ => {
+                return Err(op.error(format!(
+                    "Expected ':=' or '+=' after 'i', found '{}'",
+                    op.text
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// `if condition then statement` or `if condition begin`.
+    fn if_statement(&mut self, token: &Token) -> Result<(), AssembleError> {
+        let condition = self.condition(token)?;
+        let keyword = self.next(token)?;
+        match keyword.text.as_str() {
+            "then" => {
+                self.emit_skip_unless(condition);
+                Ok(())
+            }
+            "begin" => {
+                self.emit_skip_unless(condition.negated());
+                let jump = self.program.len();
+                self.emit(JUMP(0));
+                self.blocks.push(Block::If {
+                    jump,
+                    token: token.clone(),
+                });
+                Ok(())
+            }
+            _ => Err(keyword.error(format!(
+                "Expected 'then' or 'begin', found '{}'",
+                keyword.text
+            ))),
+        }
+    }
+
+    fn condition(&mut self, after: &Token) -> Result<Condition, AssembleError> {
+        let x_token = self.tokens.front().cloned();
+        let x = self.register(after)?;
+        let op = self.next(after)?;
+        let comparison = match op.text.as_str() {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            ">" => Comparison::Greater,
+            "<=" => Comparison::LessOrEqual,
+            ">=" => Comparison::GreaterOrEqual,
+            "key" => Comparison::Key,
+            "-key" => Comparison::NotKey,
+            _ => return Err(op.error(format!("Expected a comparison, found '{}'", op.text))),
+        };
+
+        let (operand, operand_token) =
+            if comparison == Comparison::Key || comparison == Comparison::NotKey {
+                (Operand::None, None)
+            } else {
+                let token = self.next(&op)?;
+                let operand = match self.register_of(&token) {
+                    Some(y) => Operand::Register(y),
+                    None => Operand::Value(self.byte_of(&token)?),
+                };
+                (operand, Some(token))
+            };
+
+        // Ordering comparisons subtract into VF, so they can't read it. See `emit_skip_unless`.
+        let is_ordering = !matches!(
+            comparison,
+            Comparison::Equal | Comparison::NotEqual | Comparison::Key | Comparison::NotKey
+        );
+        let vf_token = match (x, operand) {
+            (0xF, _) => x_token,
+            (_, Operand::Register(0xF)) => operand_token,
+            _ => None,
+        };
+        if let (true, Some(token)) = (is_ordering, vf_token) {
+            return Err(token.error(format!(
+                "'{}' can't compare vf, which it uses for the result",
+                op.text
+            )));
+        }
+
+        Ok(Condition {
+            x,
+            comparison,
+            operand,
+        })
+    }
+
+    /// Emit instructions that skip the next one unless the condition holds.
+    fn emit_skip_unless(&mut self, condition: Condition) {
+        let Condition {
+            x,
+            comparison,
+            operand,
+        } = condition;
+
+        match (comparison, operand) {
+            (Comparison::Equal, Operand::Register(y)) => self.emit(SKRNE(x, y)),
+            (Comparison::Equal, Operand::Value(n)) => self.emit(SKNE(x, n)),
+            (Comparison::NotEqual, Operand::Register(y)) => self.emit(SKRE(x, y)),
+            (Comparison::NotEqual, Operand::Value(n)) => self.emit(SKE(x, n)),
+            (Comparison::Key, _) => self.emit(SKUP(x)),
+            (Comparison::NotKey, _) => self.emit(SKPR(x)),
+
+            // The rest compare with a subtraction into VF, whose flag is set when there's no
+            // borrow: SUBN VF, VX gives VF = 1 when VX >= operand.
+            (Comparison::Less, _) => {
+                self.emit_at_least(x, operand);
+                self.emit(SKNE(0xF, 0));
+            }
+            (Comparison::GreaterOrEqual, _) => {
+                self.emit_at_least(x, operand);
+                self.emit(SKE(0xF, 0));
+            }
+            // VX > VY is VY < VX.
+            (Comparison::Greater, Operand::Register(y)) => {
+                self.emit_at_least(y, Operand::Register(x));
+                self.emit(SKNE(0xF, 0));
+            }
+            (Comparison::LessOrEqual, Operand::Register(y)) => {
+                self.emit_at_least(y, Operand::Register(x));
+                self.emit(SKE(0xF, 0));
+            }
+            // VX > N is VX >= N + 1, which never holds for N = 255.
+            (Comparison::Greater, Operand::Value(0xFF)) => self.emit(SKRE(0xF, 0xF)),
+            (Comparison::LessOrEqual, Operand::Value(0xFF)) => (),
+            (Comparison::Greater, Operand::Value(n)) => {
+                self.emit_at_least(x, Operand::Value(n + 1));
+                self.emit(SKE(0xF, 0));
+            }
+            (Comparison::LessOrEqual, Operand::Value(n)) => {
+                self.emit_at_least(x, Operand::Value(n + 1));
+                self.emit(SKNE(0xF, 0));
+            }
+            (_, Operand::None) => unreachable!("Only key comparisons have no operand"),
+        }
+    }
+
+    /// Set VF to 1 if VX >= the operand, otherwise 0.
+    fn emit_at_least(&mut self, x: usize, operand: Operand) {
+        match operand {
+            Operand::Register(y) => self.emit(MOVE(0xF, y)),
+            Operand::Value(n) => self.emit(LOAD(0xF, n)),
+            Operand::None => (),
+        }
+        self.emit(SUBN(0xF, x));
+    }
+
+    /// Point the jump at `offset` to the current address, where `token`'s statement is.
+    fn patch_jump(&mut self, offset: usize, token: &Token) -> Result<(), AssembleError> {
+        let address = twelve_bit(token, &format!("'{}'", token.text), self.address())?;
+        let opcode = JUMP(address).encode();
+        self.program[offset] = (opcode >> 8) as u8;
+        self.program[offset + 1] = opcode as u8;
+        Ok(())
+    }
+
+    /// `:macro name params { body }`.
+    fn define_macro(&mut self, token: &Token) -> Result<(), AssembleError> {
+        let name = self.new_name(token)?;
+        let mut params = Vec::new();
+        loop {
+            let param = self.next(&name)?;
+            if param.text == "{" {
+                break;
+            }
+            params.push(param.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        while depth > 0 {
+            let word = self
+                .tokens
+                .pop_front()
+                .ok_or_else(|| name.error(format!("Macro '{}' has no closing '}}'", name.text)))?;
+            match word.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            if depth > 0 {
+                body.push(word);
+            }
+        }
+
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    /// Replace a macro's name and arguments with its body, with the arguments substituted.
+    fn expand_macro(&mut self, token: &Token) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(token.error(format!(
+                "Macro '{}' expanded too many times. Does it use itself?",
+                token.text
+            )));
+        }
+
+        let param_count = self.macros[&token.text].params.len();
+        let mut args = Vec::new();
+        for _ in 0..param_count {
+            args.push(self.next(token)?);
+        }
+
+        let m = &self.macros[&token.text];
+        let expansion: Vec<Token> = m
+            .body
+            .iter()
+            .map(|word| match m.params.iter().position(|p| *p == word.text) {
+                Some(i) => Token {
+                    text: args[i].text.clone(),
+                    ..word.clone()
+                },
+                None => word.clone(),
+            })
+            .collect();
+        for word in expansion.into_iter().rev() {
+            self.tokens.push_front(word);
+        }
+        Ok(())
+    }
+}
+
+impl Condition {
+    /// The condition that holds when this one doesn't.
+    fn negated(self) -> Self {
+        let comparison = match self.comparison {
+            Comparison::Equal => Comparison::NotEqual,
+            Comparison::NotEqual => Comparison::Equal,
+            Comparison::Less => Comparison::GreaterOrEqual,
+            Comparison::GreaterOrEqual => Comparison::Less,
+            Comparison::Greater => Comparison::LessOrEqual,
+            Comparison::LessOrEqual => Comparison::Greater,
+            Comparison::Key => Comparison::NotKey,
+            Comparison::NotKey => Comparison::Key,
+        };
+        Condition { comparison, ..self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+    use crate::debug::Register;
+    use crate::quirks::Platform;
+
+    fn opcodes(program: &[u8]) -> Vec<u16> {
+        program
+            .chunks(2)
+            .map(|pair| (pair[0] as u16) << 8 | pair[1] as u16)
+            .collect()
+    }
+
+    /// Assemble and run a program until it reaches a jump to itself.
+    fn run(source: &str) -> Chip8 {
+        let program = assemble(source).unwrap();
+        let mut machine = Chip8::new(Platform::default());
+        machine.load_program(&program).unwrap();
+
+        for _ in 0..1000 {
+            if machine.next_opcode() == Some(0x1000 | machine.register(Register::Pc)) {
+                break;
+            }
+            machine.tick().unwrap();
+        }
+        machine
+    }
+
+    /// Instructions assemble to their opcodes, with labels used before and after they're defined.
+    #[test]
+    fn test_assemble_instructions() {
+        let source = "
+            : main
+                clear
+                i := sprite          # Defined later.
+                v0 := 5  v1 := v0  v1 += 0x10  v2 -= 1
+                sprite v0 v1 2
+                loadflags v3         # SUPER-CHIP.
+                draw
+                jump main
+            : draw
+                i := hex v2
+                return
+            : sprite
+                0b11110000 0x90
+        ";
+        assert_eq!(
+            opcodes(&assemble(source).unwrap()),
+            vec![
+                0x00E0, 0xA218, 0x6005, 0x8100, 0x7110, 0x72FF, 0xD012, 0xF385, 0x2214, 0x1200,
+                0xF229, 0x00EE, 0xF090,
+            ]
+        );
+
+        // A main that isn't first is jumped to.
+        assert_eq!(
+            opcodes(&assemble(": data 1 2 : main jump data").unwrap()),
+            vec![0x1204, 0x0102, 0x1202]
+        );
+    }
+
+    /// Constants, aliases and macros are substituted where they're used.
+    #[test]
+    fn test_assemble_definitions() {
+        let source = "
+            :const SPEED 3
+            :alias x v4
+            :macro move reg amount { reg += amount }
+            : main
+                x := SPEED
+                move x SPEED
+                move v5 x
+        ";
+        assert_eq!(
+            opcodes(&assemble(source).unwrap()),
+            vec![0x6403, 0x7403, 0x8544]
+        );
+    }
+
+    /// Control structures branch and loop as they read.
+    #[test]
+    fn test_assemble_control_structures() {
+        let machine = run("
+            : main
+                v0 := 0  v1 := 0  v2 := 0  v3 := 0  v4 := 0
+                loop
+                    v0 += 1
+                    if v0 == 3 then v1 += 1
+                    if v0 < 5 begin
+                        v2 += 1
+                    else
+                        v3 += 1
+                    end
+                    if v0 >= 8 then v4 += 1
+                    while v0 != 9
+                again
+            : done
+                jump done
+        ");
+        assert_eq!(machine.register(Register::V(0)), 9);
+        assert_eq!(machine.register(Register::V(1)), 1);
+        assert_eq!(machine.register(Register::V(2)), 4);
+        assert_eq!(machine.register(Register::V(3)), 5);
+        assert_eq!(machine.register(Register::V(4)), 2);
+    }
+
+    /// Every comparison holds for exactly the values it should, against bytes and registers.
+    #[test]
+    fn test_assemble_comparisons() {
+        for &(op, expected) in &[
+            ("==", [false, true, false]),
+            ("!=", [true, false, true]),
+            ("<", [true, false, false]),
+            (">", [false, false, true]),
+            ("<=", [true, true, false]),
+            (">=", [false, true, true]),
+        ] {
+            for (v0, &holds) in [4, 5, 6].iter().zip(expected.iter()) {
+                let machine = run(&format!(
+                    "v0 := {} v1 := 5 v2 := 0 v3 := 0
+                    if v0 {} 5 then v2 := 1
+                    if v0 {} v1 then v3 := 1
+                    : done jump done",
+                    v0, op, op
+                ));
+                let result = (holds as usize, holds as usize);
+                assert_eq!(
+                    (
+                        machine.register(Register::V(2)),
+                        machine.register(Register::V(3))
+                    ),
+                    result,
+                    "{} {} 5",
+                    v0,
+                    op
+                );
+            }
+        }
+
+        let machine = run("v0 := 255 v1 := 0 if v0 > 255 then v1 := 1 : done jump done");
+        assert_eq!(machine.register(Register::V(1)), 0);
+    }
+
+    /// Addresses map to the lines their statements are on, and lines to their first statement.
+    #[test]
+    fn test_source_map() {
+        let source = "
+            : data 0xFF        # Line 2. Jumped over, from 0x200.
+            : main
+                v0 := 1        # Line 4.
+
+                if v0 == 1 then v1 := 2
+        ";
+        let (program, map) = assemble_with_source_map(source).unwrap();
+        assert_eq!(program.len(), 9);
+
+        assert_eq!(map.line(0x200), None);
+        assert_eq!(map.line(0x202), Some(2));
+        assert_eq!(map.line(0x203), Some(4));
+        assert_eq!(map.line(0x205), Some(6));
+        assert_eq!(map.line(0x207), Some(6));
+        assert_eq!(map.line(0x209), None);
+
+        assert_eq!(map.address(4), Some(0x203));
+        assert_eq!(map.address(5), Some(0x205));
+        assert_eq!(map.address(7), None);
+    }
+
+    /// Errors point to the line and column of the word at fault.
+    #[test]
+    fn test_assemble_errors() {
+        let error = |source: &str| assemble(source).unwrap_err().to_string();
+
+        assert_eq!(
+            error(": main\n  v0 := 300"),
+            "line 2, column 9: 300 is out of range, expected -128 to 255"
+        );
+        assert_eq!(
+            error("jump nowhere"),
+            "line 1, column 6: Unknown name 'nowhere'"
+        );
+        assert_eq!(
+            error("v0 += 1\n  v0 * 2"),
+            "line 2, column 6: Expected an operator such as ':=' or '+=', found '*'"
+        );
+        assert_eq!(
+            error("loop\n  v0 += 1"),
+            "line 1, column 1: 'loop' has no matching 'again'"
+        );
+        assert_eq!(
+            error(": a\n: a"),
+            "line 2, column 3: 'a' is already defined"
+        );
+        assert_eq!(
+            error("if v0 == 1 v1 := 2"),
+            "line 1, column 12: Expected 'then' or 'begin', found 'v1'"
+        );
+        assert_eq!(
+            error("if vf < 3 then v0 := 1"),
+            "line 1, column 4: '<' can't compare vf, which it uses for the result"
+        );
+        assert_eq!(
+            error("if v0 >= vF then v0 := 1"),
+            "line 1, column 10: '>=' can't compare vf, which it uses for the result"
+        );
+
+        // Enough data to push what follows past 0xFFF.
+        let padding = "0 ".repeat(0xE00);
+        assert_eq!(
+            error(&format!("jump far\n{}\n: far", padding)),
+            "line 1, column 6: Label 'far' is at 0x1002, beyond the 12-bit addresses this \
+             instruction takes"
+        );
+        assert_eq!(
+            error(&format!("if v0 == 1 begin\n{}\nend", padding)),
+            "line 3, column 1: 'end' is at 0x1004, beyond the 12-bit addresses this instruction \
+             takes"
+        );
+        assert_eq!(
+            error(&format!("{}\nloop\n  v0 += 1\nagain", padding)),
+            "line 2, column 1: 'loop' is at 0x1000, beyond the 12-bit addresses this instruction \
+             takes"
+        );
+    }
+}
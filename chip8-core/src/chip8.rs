@@ -0,0 +1,1375 @@
+#![allow(non_snake_case)]
+use crate::coverage::Coverage;
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+use crate::profile::Profile;
+use crate::quirks::{Platform, Quirks};
+use crate::trace::Trace;
+use crate::watch::{WatchHit, Watchpoint};
+use pretty_hex::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+/// What happened during a single tick of the Chip8.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepOutcome {
+    Executed,         // An opcode was run.
+    WaitingForInput,  // KEYD is waiting for a key press.
+    WaitingForVblank, // DRAW is waiting for the display.
+    Exited,           // The program has exited.
+    Watchpoint,       // An opcode was run that touched a watchpoint. See `take_watch_hit`.
+}
+
+#[derive(Clone)]
+pub struct Chip8 {
+    pub audio_pattern: [usize; 16],    // XO-CHIP 128 1-bit audio samples.
+    pub(crate) cycle: usize,           // The current cycle count.
+    pub(crate) delay_timer: usize,     // Delay timer ticks down to 0 at 60hz.
+    pub(crate) index_register: usize,  // 16-bit register (for memory addressing) aka I
+    pub(crate) keyd_register: usize,   // 8 bit register for the KEYD opcode.
+    pub(crate) keys: [bool; 16],       // Array of key states. Index is the key.
+    pub(crate) memory: Vec<usize>,     // 4k (64k for XO-CHIP) of 8 bit memory.
+    pub pitch: usize,                  // XO-CHIP audio pattern playback pitch.
+    pub(crate) platform: Platform,     // Which interpreter's instruction set to run.
+    pub(crate) program_counter: usize, // 16-bit program counter.
+    pub graphics_buffer: Vec<u8>,      // 64x32 or 128x64 (hires) pixels, row-major. Bit per plane.
+    pub has_audio_update: bool,        // Audio pattern changed. Emulator clears after using it.
+    pub has_exited: bool,              // Has the program asked the interpreter to exit?
+    pub has_graphics_update: bool,     // Display changed. Emulator clears after drawing it.
+    pub(crate) hires: bool,            // Is the SUPER-CHIP 128x64 display mode on?
+    pub last_opcode: usize,            // Last run opcode.
+    pub quirks: Quirks,                // Interpreter-specific opcode behaviours.
+    pub(crate) rom_hash: u64,          // Hash of the loaded ROM, to match save states to it.
+    pub rom_size: usize,               // Size of loaded ROM in bytes.
+    pub rpl_flags: [usize; 16],        // SUPER-CHIP user flags, persisted between runs.
+    pub(crate) selected_planes: u8,    // XO-CHIP bitmask of display planes drawn to.
+    pub wait_for_input: bool,          // Wait for input before next tick?
+    pub(crate) wait_for_vblank: bool,  // Wait for the next timer tick before next opcode?
+    pub(crate) registers: [usize; 16], // 16  8-bit registers: V0 - VF
+    pub(crate) rng: StdRng,            // Random numbers for RAND.
+    pub sound_timer: usize,            // Ticks down to 0 at 60hz. If not 0, a tone plays.
+    pub(crate) stack_pointer: usize,   // stack pointer for which address currently on.
+    pub(crate) stack: [usize; 16],     // stack to store return addresses.
+    pub(crate) watchpoints: Vec<Watchpoint>, // Checked by every opcode run.
+    pub(crate) watch_hit: Option<WatchHit>, // First watchpoint touched by the last opcode.
+    pub(crate) trace: Option<Trace>,   // Opcodes run, while tracing.
+    pub(crate) profile: Option<Profile>, // Where cycles went, while profiling.
+    pub(crate) coverage: Option<Coverage>, // Bytes used as code and data, while recording.
+}
+
+/// Core feature implenentation.
+impl Chip8 {
+    // Memory addresses (start, end).
+    // const ADDR_INTERPRETER: (usize, usize) = (0x000, 0x1FF);
+    const ADDRESS_FONT: usize = 0x050; // Where the font is stored in memory.
+    const ADDRESS_BIG_FONT: usize = 0x0A0; // Where the SUPER-CHIP font is stored in memory.
+    pub(crate) const ADDRESS_ROM: usize = 0x200;
+    pub(crate) const OPCODE_SIZE: usize = 2;
+    const DEFAULT_PITCH: usize = 64; // XO-CHIP pitch that plays the pattern at 4000hz.
+
+    #[rustfmt::skip]
+    /// 4x5 raster font. Each hex character represents a row of pixels.
+    /// Only the least significant four pixels are used.
+    const FONT: [usize; 80] = [
+        	0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+        	0x20, 0x60, 0x20, 0x20, 0x70, // 1
+        	0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+        	0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+        	0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+        	0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+        	0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+        	0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+        	0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+        	0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+        	0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+        	0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+        	0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+        	0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+        	0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+        	0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+    ];
+
+    #[rustfmt::skip]
+    /// SUPER-CHIP 8x10 raster font. Each hex character represents a row of pixels.
+    /// The original only had digits. A-F are the ones Octo added.
+    const BIG_FONT: [usize; 160] = [
+        	0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+        	0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+        	0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+        	0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+        	0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+        	0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+        	0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+        	0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+        	0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+        	0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+        	0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+        	0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+        	0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+        	0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+        	0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+        	0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+    ];
+
+    /// Create a machine using the quirks preset for a given platform.
+    pub fn new(platform: Platform) -> Self {
+        // Load font into memory.
+        let mut memory = vec![0; Chip8::memory_size(platform)];
+        Chip8::FONT
+            .iter()
+            .enumerate()
+            .for_each(|(i, &n)| memory[i + Chip8::ADDRESS_FONT] = n);
+        Chip8::BIG_FONT
+            .iter()
+            .enumerate()
+            .for_each(|(i, &n)| memory[i + Chip8::ADDRESS_BIG_FONT] = n);
+
+        Self {
+            audio_pattern: [0; 16],
+            cycle: 0,
+            delay_timer: 0,
+            graphics_buffer: vec![0; 64 * 32],
+            has_audio_update: false,
+            has_exited: false,
+            has_graphics_update: false,
+            hires: false,
+            index_register: 0,
+            keyd_register: 0,
+            keys: [false; 16],
+            last_opcode: 0,
+            memory,
+            pitch: Chip8::DEFAULT_PITCH,
+            platform,
+            program_counter: Chip8::ADDRESS_ROM,
+            quirks: platform.quirks(),
+            registers: [0; 16],
+            rng: StdRng::from_entropy(),
+            rom_hash: 0,
+            rom_size: 0,
+            rpl_flags: [0; 16],
+            selected_planes: 1,
+            sound_timer: 0,
+            stack_pointer: 0,
+            stack: [0; 16],
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            trace: None,
+            profile: None,
+            coverage: None,
+            wait_for_input: false,
+            wait_for_vblank: false,
+        }
+    }
+
+    /// Load a ROM file into memory at 0x200, where execution begins.
+    pub fn load_rom<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let mut buffer = Vec::new();
+        let mut f = File::open(path)?;
+
+        f.read_to_end(&mut buffer)?;
+        self.load_program(&buffer)
+    }
+
+    /// Load a program's bytes into memory at 0x200, where execution begins.
+    pub fn load_program(&mut self, program: &[u8]) -> io::Result<()> {
+        let start = Chip8::ADDRESS_ROM;
+
+        if start + program.len() > self.memory.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "ROM is {} bytes, which does not fit in memory.",
+                    program.len()
+                ),
+            ));
+        }
+
+        for (idx, &value) in program.iter().enumerate() {
+            self.memory[idx + start] = value as usize;
+        }
+
+        self.rom_hash = hash_rom(program);
+        self.rom_size = program.len();
+        Ok(())
+    }
+
+    /// XO-CHIP can address 64k of memory. The rest only 4k.
+    pub(crate) fn memory_size(platform: Platform) -> usize {
+        if platform == Platform::XoChip {
+            0x10000
+        } else {
+            0x1000
+        }
+    }
+
+    /// Seed the random numbers RAND produces, so runs are repeatable.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Width and height of the display in its current mode.
+    /// `graphics_buffer` is row-major with this width. Each pixel is a bitmask of the display
+    /// planes it is set on: 0 is off, 1 is on for plain CHIP8.
+    pub fn display_size(&self) -> (usize, usize) {
+        if self.hires {
+            (128, 64)
+        } else {
+            (64, 32)
+        }
+    }
+
+    /// Does the platform support the XO-CHIP instructions?
+    fn has_xochip_instructions(&self) -> bool {
+        self.platform == Platform::XoChip
+    }
+
+    /// XO-CHIP audio pattern playback rate in hz, derived from the pitch register.
+    pub fn audio_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// Decrement both sound and delay timers.
+    /// This should be getting called at 60hz, which `end_frame` does.
+    pub fn decrement_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    /// Set the current state of all keys into the machine's memory.
+    /// Each index is a key, 0-F. If KEYD is waiting, the lowest pressed key resumes it.
+    pub fn set_keys(&mut self, keys: [bool; 16]) {
+        // If waiting for input and any key is pressed, continue self.KEYD opcode.
+        if self.wait_for_input {
+            for (key_name, &is_pressed) in keys.iter().enumerate() {
+                if is_pressed {
+                    self.KEYD_RESUME(key_name);
+                    break;
+                }
+            }
+        };
+
+        self.keys = keys;
+    }
+
+    /// Advance the machine one cycle. This runs at most one opcode. Timers are left alone, see
+    /// `run_frame` and `end_frame`.
+    pub fn tick(&mut self) -> Result<StepOutcome, Chip8Error> {
+        self.cycle += 1;
+
+        // Every tick, process 1 opcode unless awaiting input or the display, or the program exited.
+        if self.has_exited {
+            Ok(StepOutcome::Exited)
+        } else if self.wait_for_input {
+            self.profile_wait();
+            Ok(StepOutcome::WaitingForInput)
+        } else if self.wait_for_vblank {
+            self.profile_wait();
+            Ok(StepOutcome::WaitingForVblank)
+        } else {
+            self.watch_hit = None;
+            let registers = self.watched_registers();
+            let (pc, traced) = (self.program_counter, self.trace_before());
+            self.execute_opcode()?;
+            self.watch_registers(pc, &registers);
+            if let Some(before) = traced {
+                self.trace_after(pc, &before);
+            }
+            self.profile_instruction(pc);
+            self.cover_instruction(pc);
+
+            if self.watch_hit.is_some() {
+                Ok(StepOutcome::Watchpoint)
+            } else {
+                Ok(StepOutcome::Executed)
+            }
+        }
+    }
+
+    /// Run one 60hz frame: `instructions` ticks, then `end_frame`.
+    /// Returns the outcome of the last tick. Stops early if the program exits or a watchpoint is
+    /// touched.
+    pub fn run_frame(&mut self, instructions: usize) -> Result<StepOutcome, Chip8Error> {
+        let mut outcome = StepOutcome::Executed;
+        for _ in 0..instructions {
+            outcome = self.tick()?;
+            if outcome == StepOutcome::Exited || outcome == StepOutcome::Watchpoint {
+                break;
+            }
+        }
+
+        self.end_frame();
+        Ok(outcome)
+    }
+
+    /// Decrement the timers, once per 60hz frame. This is also the vertical blank DRAW may be
+    /// waiting for.
+    pub fn end_frame(&mut self) {
+        self.decrement_timers();
+        self.wait_for_vblank = false;
+    }
+
+    /// Run the opcode at the program counter. On error, the program counter is left pointing at
+    /// the failed opcode.
+    pub fn execute_opcode(&mut self) -> Result<(), Chip8Error> {
+        use Instruction::*;
+
+        let opcode = self.get_opcode()?;
+        self.last_opcode = opcode;
+        let instruction = Instruction::decode_for(opcode as u16, self.platform)
+            .map_err(|_| self.unknown_opcode())?;
+
+        match instruction {
+            SCD(n) => self.SCD(n),
+            SCU(n) => self.SCU(n),
+            CLR => self.CLR(),
+            RTS => self.RTS()?,
+            SCR => self.SCR(),
+            SCL => self.SCL(),
+            EXIT => self.EXIT(),
+            LOW => self.LOW(),
+            HIGH => self.HIGH(),
+            SYS(nnn) => self.SYS(nnn)?,
+            JUMP(nnn) => self.JUMP(nnn),
+            CALL(nnn) => self.CALL(nnn)?,
+            SKE(x, nn) => self.SKE(x, nn),
+            SKNE(x, nn) => self.SKNE(x, nn),
+            SKRE(x, y) => self.SKRE(x, y),
+            SAVE(x, y) => self.SAVE(x, y)?,
+            LOADR(x, y) => self.LOADR(x, y)?,
+            LOAD(x, nn) => self.LOAD(x, nn),
+            ADD(x, nn) => self.ADD(x, nn),
+            MOVE(x, y) => self.MOVE(x, y),
+            OR(x, y) => self.OR(x, y),
+            AND(x, y) => self.AND(x, y),
+            XOR(x, y) => self.XOR(x, y),
+            ADDR(x, y) => self.ADDR(x, y),
+            SUB(x, y) => self.SUB(x, y),
+            SHR(x, y) => self.SHR(x, y),
+            SUBN(x, y) => self.SUBN(x, y),
+            SHL(x, y) => self.SHL(x, y),
+            SKRNE(x, y) => self.SKRNE(x, y),
+            LOADI(nnn) => self.LOADI(nnn),
+            JUMPI(nnn) => self.JUMPI(nnn),
+            RAND(x, nn) => self.RAND(x, nn),
+            DRAWL(x, y) => self.DRAWL(x, y)?,
+            DRAW(x, y, n) => self.DRAW(x, y, n)?,
+            SKPR(x) => self.SKPR(x),
+            SKUP(x) => self.SKUP(x),
+            LOADIL => self.LOADIL()?,
+            PLANE(x) => self.PLANE(x),
+            AUDIO => self.AUDIO()?,
+            MOVED(x) => self.MOVED(x),
+            KEYD(x) => self.KEYD(x),
+            LOADD(x) => self.LOADD(x),
+            LOADS(x) => self.LOADS(x),
+            ADDI(x) => self.ADDI(x),
+            LDSPR(x) => self.LDSPR(x),
+            LDHSPR(x) => self.LDHSPR(x),
+            BCD(x) => self.BCD(x)?,
+            PITCH(x) => self.PITCH(x),
+            STOR(x) => self.STOR(x)?,
+            READ(x) => self.READ(x)?,
+            SRPL(x) => self.SRPL(x),
+            LRPL(x) => self.LRPL(x),
+        };
+
+        // Increment PC unless opcode is JUMP, JUMPI, or CALL.
+        if !matches!(instruction, JUMP(_) | JUMPI(_) | CALL(_)) {
+            self.program_counter += Chip8::OPCODE_SIZE;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn get_opcode(&self) -> Result<usize, Chip8Error> {
+        if self.program_counter + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfBounds {
+                pc: self.program_counter,
+                opcode: self.last_opcode,
+            });
+        }
+
+        // Get opcode by combining two bits from memory.
+        let low = self.memory[self.program_counter + 1];
+        let high = self.memory[self.program_counter];
+        Ok(((high) << 8) | low)
+    }
+
+    fn unknown_opcode(&self) -> Chip8Error {
+        Chip8Error::UnknownOpcode {
+            pc: self.program_counter,
+            opcode: self.last_opcode,
+        }
+    }
+
+    /// Error unless `len` bytes of memory starting at `start` exist.
+    pub(crate) fn check_memory(&self, start: usize, len: usize) -> Result<(), Chip8Error> {
+        if start
+            .checked_add(len)
+            .is_none_or(|end| end > self.memory.len())
+        {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                pc: self.program_counter,
+                opcode: self.last_opcode,
+                address: start.max(self.memory.len()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Note an opcode reading `len` bytes of memory from `start`, for read watchpoints and
+    /// coverage.
+    fn note_read(&mut self, start: usize, len: usize) {
+        self.watch_read(start, len);
+        self.cover_read(start, len);
+    }
+}
+/// Opcode implementation.
+impl Chip8 {
+    /// Skip the next instruction. XO-CHIP's F000 NNNN is twice as long as the rest.
+    fn skip_next(&mut self) {
+        self.program_counter += Chip8::OPCODE_SIZE;
+        if self.has_xochip_instructions() && self.get_opcode() == Ok(0xF000) {
+            self.program_counter += Chip8::OPCODE_SIZE;
+        }
+    }
+
+    /// Clear the selected planes of the graphics buffer.
+    fn CLR(&mut self) {
+        let planes = self.selected_planes;
+        self.graphics_buffer.iter_mut().for_each(|p| *p &= !planes);
+        self.has_graphics_update = true;
+    }
+
+    /// Return from subroutine.
+    fn RTS(&mut self) -> Result<(), Chip8Error> {
+        if self.stack_pointer == 0 {
+            return Err(Chip8Error::StackUnderflow {
+                pc: self.program_counter,
+                opcode: self.last_opcode,
+            });
+        }
+
+        self.stack_pointer -= 1;
+        self.program_counter = self.stack[self.stack_pointer];
+        Ok(())
+    }
+
+    // Jump to machine code routine at nnn. Not implemented in modern CHIP8 emulators.
+    fn SYS(&mut self, _nnn: usize) -> Result<(), Chip8Error> {
+        Err(self.unknown_opcode())
+    }
+
+    /// Jump PC to NNN.
+    fn JUMP(&mut self, nnn: usize) {
+        self.program_counter = nnn;
+    }
+
+    /// Call subroutine at NNN.
+    fn CALL(&mut self, nnn: usize) -> Result<(), Chip8Error> {
+        if self.stack_pointer == self.stack.len() {
+            return Err(Chip8Error::StackOverflow {
+                pc: self.program_counter,
+                opcode: self.last_opcode,
+            });
+        }
+
+        // Maintain current PC in the stack to be able to return from subroutine.
+        self.stack[self.stack_pointer] = self.program_counter;
+        self.stack_pointer += 1;
+        self.program_counter = nnn;
+        Ok(())
+    }
+
+    /// Skip next instruction if VX == NN.
+    fn SKE(&mut self, x: usize, nn: usize) {
+        if self.registers[x] == nn {
+            self.skip_next();
+        }
+    }
+
+    /// Skip next instruction if VX != NN.
+    fn SKNE(&mut self, x: usize, nn: usize) {
+        if self.registers[x] != nn {
+            self.skip_next();
+        }
+    }
+
+    /// Skip next instruction if VX == VY;
+    fn SKRE(&mut self, x: usize, y: usize) {
+        if self.registers[x] == self.registers[y] {
+            self.skip_next();
+        }
+    }
+
+    /// Set register X to NN;
+    fn LOAD(&mut self, x: usize, nn: usize) {
+        self.registers[x] = nn;
+    }
+
+    /// Add NN to VX. Carry flag isn't changed.
+    fn ADD(&mut self, x: usize, nn: usize) {
+        self.registers[x] = (self.registers[x] + nn) % 0x100
+    }
+
+    /// Write VY to VX.
+    fn MOVE(&mut self, x: usize, y: usize) {
+        self.registers[x] = self.registers[y];
+    }
+
+    /// VX = VX | VY.
+    fn OR(&mut self, x: usize, y: usize) {
+        self.registers[x] |= self.registers[y];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    /// VX = VX & VY.
+    fn AND(&mut self, x: usize, y: usize) {
+        self.registers[x] &= self.registers[y];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    /// VX = VX ^ VY.
+    fn XOR(&mut self, x: usize, y: usize) {
+        self.registers[x] ^= self.registers[y];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    /// Add VX to VY. Set VF to 1 if overflow, else 0.
+    fn ADDR(&mut self, x: usize, y: usize) {
+        let vx = self.registers[x];
+        let vy = self.registers[y];
+
+        self.registers[0xF] = if vx + vy > 0xFF { 1 } else { 0 };
+        self.registers[x] = (vx + vy) % 0x100;
+    }
+
+    fn SUB(&mut self, x: usize, y: usize) {
+        let vx = self.registers[x];
+        let vy = self.registers[y];
+
+        // Wrapping subtract as u8 to ensure it wraps around, as intended by the hardware.
+        self.registers[x] = (vx as u8).wrapping_sub(vy as u8) as usize;
+
+        self.registers[0xF] = if vx > vy { 1 } else { 0 };
+    }
+
+    /// Store LSB of VX to VF then bit shift right (divide by 2).
+    /// The original interpreter shifted VY into VX. Later ones ignore y and shift VX in place.
+    fn SHR(&mut self, x: usize, y: usize) {
+        let vx = if self.quirks.shift_uses_vy {
+            self.registers[y]
+        } else {
+            self.registers[x]
+        };
+        self.registers[x] = vx >> 1;
+        self.registers[0xF] = vx & 0x1;
+    }
+
+    /// Set VX to VY - VX. VF = 0 if borrow else 1.
+    fn SUBN(&mut self, x: usize, y: usize) {
+        let vx = self.registers[x];
+        let vy = self.registers[y];
+
+        // Wrapping subtract as u8, as SUB does, so VX stays a byte.
+        self.registers[x] = (vy as u8).wrapping_sub(vx as u8) as usize;
+        self.registers[0xF] = if vx > vy { 0 } else { 1 }
+    }
+
+    /// Store most-significant bit of VX in VF then shift VX left by 1 (multiply by 2).
+    /// The original interpreter shifted VY into VX. Later ones ignore y and shift VX in place.
+    fn SHL(&mut self, x: usize, y: usize) {
+        let vx = if self.quirks.shift_uses_vy {
+            self.registers[y]
+        } else {
+            self.registers[x]
+        };
+        // Mask by 0xFF to prevent values larger than 8 bits.
+        self.registers[x] = (vx << 1) & 0xFF;
+        self.registers[0xF] = (vx & 0x80) >> 7;
+    }
+
+    /// Skip next instruction if VX != VY.
+    fn SKRNE(&mut self, x: usize, y: usize) {
+        if self.registers[x] != self.registers[y] {
+            self.skip_next();
+        }
+    }
+
+    /// Set index register to NNN.
+    fn LOADI(&mut self, nnn: usize) {
+        self.index_register = nnn;
+    }
+
+    /// Jump PC to NNN + V0. Some interpreters use VX instead, X being the high nibble of NNN.
+    fn JUMPI(&mut self, nnn: usize) {
+        let offset_register = if self.quirks.jump_with_vx {
+            (nnn & 0xF00) >> 8
+        } else {
+            0
+        };
+        self.program_counter = self.registers[offset_register] + nnn;
+    }
+
+    /// Set VX to result of bitwise: NN & RANDOM
+    fn RAND(&mut self, x: usize, nn: usize) {
+        let rand = self.rng.gen_range(0, 0x100) & nn;
+        self.registers[x] = rand;
+    }
+
+    /// Draws N sprite lines from memory[I] to coordinates (VX, VY). VF is set high if collision.
+    /// The starting coordinates always wrap. Pixels past the edge are clipped or wrapped depending
+    /// on quirks.
+    fn DRAW(&mut self, x: usize, y: usize, n: usize) -> Result<(), Chip8Error> {
+        self.draw_sprite(x, y, 1, n)
+    }
+
+    /// Draws a 16x16 sprite of 32 bytes from memory[I] to coordinates (VX, VY).
+    fn DRAWL(&mut self, x: usize, y: usize) -> Result<(), Chip8Error> {
+        self.draw_sprite(x, y, 2, 16)
+    }
+
+    /// Draws a sprite `width` bytes wide and `height` rows tall from memory[I] to each selected
+    /// plane. With both XO-CHIP planes selected, the second plane's sprite follows the first's.
+    fn draw_sprite(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<(), Chip8Error> {
+        let (screen_width, screen_height) = self.display_size();
+
+        let vx = self.registers[x] % screen_width;
+        let vy = self.registers[y] % screen_height;
+
+        self.registers[0xF] = 0;
+
+        // Read width * height bytes from memory starting at I, for each plane drawn to.
+        let mut start = self.index_register;
+        let planes: Vec<u8> = vec![1, 2]
+            .into_iter()
+            .filter(|&p| self.selected_planes & p > 0)
+            .collect();
+        self.check_memory(start, width * height * planes.len())?;
+        self.note_read(start, width * height * planes.len());
+
+        for plane in planes {
+            let end = start + width * height;
+
+            for (byte, &pixels) in self.memory[start..end].iter().enumerate() {
+                let row = byte / width;
+                let col_offset = (byte % width) * 8;
+
+                for col in 0..8 {
+                    // Get a pixel by masking 0x80 aka `0b10000000` and shifting the 1 right each
+                    // time. If it is 1, do collision detection and set the pixel.
+                    if pixels & 0x80 >> col > 0 {
+                        let col = vx + col_offset + col;
+                        let row = vy + row;
+
+                        if self.quirks.clip_sprites && (col >= screen_width || row >= screen_height)
+                        {
+                            continue;
+                        }
+
+                        // Get current pixel.
+                        let idx = (col % screen_width) + ((row % screen_height) * screen_width);
+                        let current_pixel = self.graphics_buffer[idx] & plane > 0;
+
+                        // If any collision, set VF to 1.
+                        if current_pixel {
+                            self.registers[0xF] = 1;
+                        }
+
+                        // Update the pixel with XOR.
+                        self.graphics_buffer[idx] ^= plane;
+                    }
+                }
+            }
+
+            start = end;
+        }
+        self.has_graphics_update = true;
+        self.wait_for_vblank = self.quirks.display_wait;
+        Ok(())
+    }
+
+    // Skip next operation if key stored at VX is pressed.
+    fn SKPR(&mut self, x: usize) {
+        let vx = self.registers[x] & 0xF;
+        if self.keys[vx] {
+            self.skip_next();
+        }
+    }
+
+    // Skip next operation if key stored at VX is not pressed.
+    fn SKUP(&mut self, x: usize) {
+        let vx = self.registers[x] & 0xF;
+        if !self.keys[vx] {
+            self.skip_next();
+        }
+    }
+
+    /// Load Delay Timer into VX.
+    fn MOVED(&mut self, x: usize) {
+        self.registers[x] = self.delay_timer;
+    }
+
+    /// Wait for the next input before resuming the remainder of this operation.
+    fn KEYD(&mut self, x: usize) {
+        self.keyd_register = x;
+        self.wait_for_input = true;
+    }
+
+    /// Set self.keyd_register to the name of the key (0-F).
+    /// This is called when keyboard input has been received.
+    fn KEYD_RESUME(&mut self, key: usize) {
+        self.registers[self.keyd_register] = key;
+        self.wait_for_input = false;
+    }
+
+    /// Set Delay Timer to VX.
+    fn LOADD(&mut self, x: usize) {
+        self.delay_timer = self.registers[x];
+    }
+
+    /// Set Sound Timer to VX.
+    fn LOADS(&mut self, x: usize) {
+        self.sound_timer = self.registers[x];
+    }
+
+    /// Add VX to I.  VF set to 1 if there is an overflow, else 0.
+    fn ADDI(&mut self, x: usize) {
+        let vx = self.registers[x];
+        let i = self.index_register;
+
+        self.registers[0xF] = if vx + i >= self.memory.len() { 1 } else { 0 };
+        self.index_register = (vx + i) % self.memory.len()
+    }
+
+    // Set I to location of sprite for character VX.
+    fn LDSPR(&mut self, x: usize) {
+        let character = self.registers[x];
+        self.index_register = Chip8::ADDRESS_FONT + character * 5; // Each character is 5 bytes.
+    }
+
+    /// Set I to location of the SUPER-CHIP 8x10 sprite for character VX.
+    fn LDHSPR(&mut self, x: usize) {
+        let character = self.registers[x];
+        self.index_register = Chip8::ADDRESS_BIG_FONT + character * 10; // Each is 10 bytes.
+    }
+
+    // Store binary-coded decimal of VX at I, I+1, I+2.
+    fn BCD(&mut self, x: usize) -> Result<(), Chip8Error> {
+        let i = self.index_register;
+        let vx = self.registers[x];
+        self.check_memory(i, 3)?;
+        self.watch_write(i, 3);
+
+        self.memory[i] = vx / 100;
+        self.memory[i + 1] = (vx % 100) / 10;
+        self.memory[i + 2] = vx % 10;
+        Ok(())
+    }
+
+    // Store registers to memory starting at I.
+    fn STOR(&mut self, x: usize) -> Result<(), Chip8Error> {
+        self.check_memory(self.index_register, x + 1)?;
+        self.watch_write(self.index_register, x + 1);
+
+        for n in 0..x + 1 {
+            self.memory[self.index_register + n] = self.registers[n];
+        }
+
+        if self.quirks.load_store_increments_i {
+            self.index_register += x + 1;
+        }
+        Ok(())
+    }
+
+    /// Populate registers V0 to VX with data starting at I.
+    fn READ(&mut self, x: usize) -> Result<(), Chip8Error> {
+        self.check_memory(self.index_register, x + 1)?;
+        self.note_read(self.index_register, x + 1);
+
+        for n in 0..x + 1 {
+            self.registers[n] = self.memory[self.index_register + n];
+        }
+
+        if self.quirks.load_store_increments_i {
+            self.index_register += x + 1;
+        }
+        Ok(())
+    }
+}
+
+/// SUPER-CHIP opcode implementation.
+impl Chip8 {
+    /// Scroll the display down N pixels.
+    fn SCD(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+
+    /// Scroll the display right 4 pixels.
+    fn SCR(&mut self) {
+        self.scroll(4, 0);
+    }
+
+    /// Scroll the display left 4 pixels.
+    fn SCL(&mut self) {
+        self.scroll(-4, 0);
+    }
+
+    /// Move the selected planes of the display by (dx, dy). Pixels scrolled in are blank.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = self.display_size();
+        let planes = self.selected_planes;
+        let previous = self.graphics_buffer.clone();
+
+        for row in 0..height {
+            for col in 0..width {
+                let src_col = col as isize - dx;
+                let src_row = row as isize - dy;
+
+                let src_pixel = if src_col >= 0
+                    && src_row >= 0
+                    && (src_col as usize) < width
+                    && (src_row as usize) < height
+                {
+                    previous[src_col as usize + src_row as usize * width]
+                } else {
+                    0
+                };
+
+                let idx = col + row * width;
+                self.graphics_buffer[idx] = (previous[idx] & !planes) | (src_pixel & planes);
+            }
+        }
+        self.has_graphics_update = true;
+    }
+
+    /// Exit the interpreter. The machine stops executing opcodes.
+    fn EXIT(&mut self) {
+        self.has_exited = true;
+    }
+
+    /// Switch to the 64x32 display mode. The display is cleared.
+    fn LOW(&mut self) {
+        self.set_hires(false);
+    }
+
+    /// Switch to the 128x64 display mode. The display is cleared.
+    fn HIGH(&mut self) {
+        self.set_hires(true);
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        let (width, height) = self.display_size();
+        self.graphics_buffer = vec![0; width * height];
+        self.has_graphics_update = true;
+    }
+
+    /// Store V0 to VX in the RPL user flags.
+    fn SRPL(&mut self, x: usize) {
+        self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x]);
+    }
+
+    /// Load V0 to VX from the RPL user flags.
+    fn LRPL(&mut self, x: usize) {
+        self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+    }
+}
+
+/// XO-CHIP opcode implementation.
+impl Chip8 {
+    /// Scroll the display up N pixels.
+    fn SCU(&mut self, n: usize) {
+        self.scroll(0, -(n as isize));
+    }
+
+    /// Store VX to VY (in either order) to memory starting at I. I is unchanged.
+    fn SAVE(&mut self, x: usize, y: usize) -> Result<(), Chip8Error> {
+        let count = Chip8::register_range(x, y).count();
+        self.check_memory(self.index_register, count)?;
+        self.watch_write(self.index_register, count);
+
+        for (offset, register) in Chip8::register_range(x, y).enumerate() {
+            self.memory[self.index_register + offset] = self.registers[register];
+        }
+        Ok(())
+    }
+
+    /// Load VX to VY (in either order) from memory starting at I. I is unchanged.
+    fn LOADR(&mut self, x: usize, y: usize) -> Result<(), Chip8Error> {
+        let count = Chip8::register_range(x, y).count();
+        self.check_memory(self.index_register, count)?;
+        self.note_read(self.index_register, count);
+
+        for (offset, register) in Chip8::register_range(x, y).enumerate() {
+            self.registers[register] = self.memory[self.index_register + offset];
+        }
+        Ok(())
+    }
+
+    /// Registers from X to Y, counting down if Y is lower.
+    fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+        if x <= y {
+            Box::new(x..=y)
+        } else {
+            Box::new((y..=x).rev())
+        }
+    }
+
+    /// Set I to the 16-bit address NNNN stored after this opcode.
+    fn LOADIL(&mut self) -> Result<(), Chip8Error> {
+        self.check_memory(self.program_counter + Chip8::OPCODE_SIZE, 2)?;
+        self.program_counter += Chip8::OPCODE_SIZE;
+        self.index_register = self.get_opcode()?;
+        Ok(())
+    }
+
+    /// Select the display planes (a bitmask, 0-3) that drawing, clearing and scrolling affect.
+    fn PLANE(&mut self, x: usize) {
+        self.selected_planes = x as u8 & 0b11;
+    }
+
+    /// Load the 16 byte audio pattern from memory starting at I.
+    fn AUDIO(&mut self) -> Result<(), Chip8Error> {
+        let i = self.index_register;
+        self.check_memory(i, 16)?;
+        self.note_read(i, 16);
+
+        self.audio_pattern.copy_from_slice(&self.memory[i..i + 16]);
+        self.has_audio_update = true;
+        Ok(())
+    }
+
+    /// Set the audio pattern playback pitch to VX.
+    fn PITCH(&mut self, x: usize) {
+        self.pitch = self.registers[x];
+        self.has_audio_update = true;
+    }
+}
+
+/// 64-bit FNV-1a hash of a ROM's bytes. Stable across builds, unlike std's hashers.
+fn hash_rom(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Debug functions.
+/// Ideally we hide this behind #[cfg(debug_assertions)] to only include in debug versions.
+/// But the actual audience for this is developers, so let's not bother.
+impl Chip8 {
+    pub fn dump_state(&self) -> String {
+        let keys: Vec<usize> = self.keys.iter().map(|&k| if k { 1 } else { 0 }).collect();
+        // The PC is shown from the start of the ROM, unless it's gone below it.
+        let pc = match self.program_counter.checked_sub(Chip8::ADDRESS_ROM) {
+            Some(offset) => format!("{:x}", offset),
+            None => format!("{:#05x} (below the ROM)", self.program_counter),
+        };
+        [
+            format!("PC:      {}\n", pc),
+            format!("SP:      {:x}\n", self.stack_pointer),
+            format!("I:       {:x}\n", self.index_register),
+            format!("Opcode:  {:#X}\n", self.last_opcode),
+            format!("Cycle #: {}\n", self.cycle),
+            format!("Registers: {:x?}\n", self.registers),
+            format!("Stack:     {:x?}\n", self.stack),
+            format!("Keys:      {:?}\n", keys),
+        ]
+        .concat()
+    }
+
+    /// Dump the loaded ROM as a formatted string.
+    pub fn dum_loaded_rom(&self) -> String {
+        let start = Chip8::ADDRESS_ROM;
+        format!(
+            "{:?}",
+            self.memory[start..start + self.rom_size]
+                .to_vec()
+                .iter()
+                .map(|&f| f as u8)
+                .collect::<Vec<u8>>()
+                .hex_dump()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_ROM_BYTES: &[usize] = &[
+        96, 0, 97, 0, 162, 34, 194, 1, 50, 1, 162, 30, 208, 20, 112, 4, 48, 64, 18, 4, 96, 0, 113,
+        4, 49, 32, 18, 4, 18, 28, 128, 64, 32, 16, 32, 64, 128, 16,
+    ];
+
+    /// Test that the machine initializes to a proper initial state.
+    #[test]
+    fn test_init() {
+        let machine = Chip8::new(Platform::default());
+        assert_eq!(machine.program_counter, Chip8::ADDRESS_ROM);
+    }
+
+    /// The PC is dumped from the start of the ROM, or as an address if it's below it.
+    #[test]
+    fn test_dump_state() {
+        let mut machine = Chip8::new(Platform::default());
+        machine.program_counter = 0x21C;
+        assert!(machine.dump_state().starts_with("PC:      1c\n"));
+        machine.program_counter = 0x002;
+        assert!(machine
+            .dump_state()
+            .starts_with("PC:      0x002 (below the ROM)\n"));
+    }
+
+    /// Test that the machine initializes to a proper zero state.
+    #[test]
+    fn test_load_rom() {
+        let mut machine = Chip8::new(Platform::default());
+        machine.load_rom("../roms/MAZE").unwrap();
+        let start = Chip8::ADDRESS_ROM;
+        let end = start + TEST_ROM_BYTES.len();
+        assert_eq!(&machine.memory[start..end], TEST_ROM_BYTES);
+    }
+
+    /// Timers should decrement by 1 each time `decrement_timers` is called, but never fall below 0.
+    #[test]
+    fn test_decrement_timers() {
+        let mut machine = Chip8::new(Platform::default());
+        machine.delay_timer = 5;
+        machine.sound_timer = 0;
+
+        machine.decrement_timers();
+
+        assert_eq!(machine.sound_timer, 0);
+        assert_eq!(machine.delay_timer, 4);
+    }
+
+    /// Timers decrement once per frame, however many instructions the frame runs.
+    #[test]
+    fn test_run_frame_timers() {
+        for &instructions in &[1, 8, 100] {
+            let mut machine = Chip8::new(Platform::Chip48);
+            machine.memory[0x200..0x202].copy_from_slice(&[0x12, 0x00]); // Loop forever.
+            machine.delay_timer = 10;
+
+            machine.run_frame(instructions).unwrap();
+            machine.run_frame(instructions).unwrap();
+
+            assert_eq!(machine.delay_timer, 8);
+            assert_eq!(machine.cycle, instructions * 2);
+        }
+    }
+
+    /// With the display wait quirk, DRAW stalls the rest of the frame.
+    #[test]
+    fn test_display_wait() {
+        let mut machine = Chip8::new(Platform::CosmacVip);
+        // DRAW, then set V0 in a loop.
+        machine.memory[0x200..0x206].copy_from_slice(&[0xD0, 0x01, 0x60, 0x01, 0x12, 0x02]);
+
+        assert_eq!(machine.run_frame(10), Ok(StepOutcome::WaitingForVblank));
+        assert_eq!(machine.registers[0], 0);
+
+        assert_eq!(machine.run_frame(10), Ok(StepOutcome::Executed));
+        assert_eq!(machine.registers[0], 1);
+    }
+
+    /// The Draw opcode should XOR black and white bits to the graphics buffer with overflow to next
+    // line. It is byte-encoded sprite-based. See specifications online for more details.
+    #[test]
+    fn test_draw() {
+        let mut machine = Chip8::new(Platform::default());
+        machine.index_register = 0x204; // Where to look for the sprite data.
+        machine.memory[0x204] = 0xCC; // 8 bits to draw:  11001100
+        machine.memory[0x205] = 0xFF; // 8 bits to draw:  11111111
+        machine.registers[0] = 0; // x coordinate
+        machine.registers[1] = 0; // y-coordinate
+
+        machine.DRAW(0, 1, 1).unwrap(); // Get x,y from 0,1 and draw a single byte of data.
+
+        // The segment of the graphics buffer is as expected. We drew four pixels at x= 0, 1, 4, 5.
+        assert_eq!(machine.graphics_buffer[0..8], [1, 1, 0, 0, 1, 1, 0, 0]);
+
+        // In this case, we draw two lines, not one.
+        machine.DRAW(0, 1, 2).unwrap();
+
+        // XORing has turned off the pixels that were on.
+        assert_eq!(machine.graphics_buffer[0..8], [0, 0, 0, 0, 0, 0, 0, 0]);
+
+        // But the second line is all on now.
+        assert_eq!(machine.graphics_buffer[64..72], [1, 1, 1, 1, 1, 1, 1, 1])
+    }
+
+    /// Sprites drawn past the right edge are clipped or wrapped to the left edge depending on
+    /// the platform's quirks.
+    #[test]
+    fn test_draw_clip_and_wrap() {
+        for &(platform, wrapped) in &[(Platform::CosmacVip, 0), (Platform::XoChip, 1)] {
+            let mut machine = Chip8::new(platform);
+            machine.index_register = 0x204;
+            machine.memory[0x204] = 0xFF;
+            machine.registers[0] = 60;
+            machine.registers[1] = 0;
+
+            machine.DRAW(0, 1, 1).unwrap();
+
+            assert_eq!(machine.graphics_buffer[60..64], [1, 1, 1, 1]);
+            assert_eq!(machine.graphics_buffer[0..4], [wrapped; 4]);
+        }
+    }
+
+    /// SUBN borrowing wraps VX around to a byte, so a following ADD doesn't overflow.
+    #[test]
+    fn test_subn_wraps() {
+        let mut machine = Chip8::new(Platform::default());
+        machine.memory[0x200..0x208]
+            .copy_from_slice(&[0x60, 0x01, 0x61, 0x00, 0x80, 0x17, 0x70, 0x01]);
+        for _ in 0..4 {
+            machine.execute_opcode().unwrap();
+        }
+        assert_eq!(machine.registers[0xF], 0);
+        assert_eq!(machine.registers[0], 0x00);
+    }
+
+    /// COSMAC VIP shifts VY into VX, CHIP-48 shifts VX in place.
+    #[test]
+    fn test_shift_quirk() {
+        let mut machine = Chip8::new(Platform::CosmacVip);
+        machine.registers[0] = 0x10;
+        machine.registers[1] = 0x03;
+        machine.SHR(0, 1);
+        assert_eq!(machine.registers[0], 0x01);
+        assert_eq!(machine.registers[0xF], 1);
+
+        let mut machine = Chip8::new(Platform::Chip48);
+        machine.registers[0] = 0x10;
+        machine.registers[1] = 0x03;
+        machine.SHR(0, 1);
+        assert_eq!(machine.registers[0], 0x08);
+        assert_eq!(machine.registers[0xF], 0);
+    }
+
+    /// Switching to hires resizes the display, and 16x16 sprites are drawn from 32 bytes.
+    #[test]
+    fn test_hires_large_sprite() {
+        let mut machine = Chip8::new(Platform::SuperChip);
+        machine.HIGH();
+        assert_eq!(machine.display_size(), (128, 64));
+        assert_eq!(machine.graphics_buffer.len(), 128 * 64);
+
+        machine.index_register = 0x300;
+        for i in 0..32 {
+            machine.memory[0x300 + i] = 0xFF;
+        }
+        machine.registers[0] = 100;
+        machine.registers[1] = 40;
+        machine.DRAWL(0, 1).unwrap();
+
+        assert_eq!(
+            machine.graphics_buffer[100 + 40 * 128..116 + 40 * 128],
+            [1; 16]
+        );
+        assert_eq!(
+            machine.graphics_buffer[100 + 55 * 128..116 + 55 * 128],
+            [1; 16]
+        );
+        assert_eq!(machine.graphics_buffer[116 + 40 * 128], 0);
+        assert_eq!(machine.graphics_buffer[100 + 56 * 128], 0);
+
+        machine.LOW();
+        assert_eq!(machine.graphics_buffer.len(), 64 * 32);
+    }
+
+    /// Scrolling moves pixels and blanks the area scrolled in from.
+    #[test]
+    fn test_scroll() {
+        let mut machine = Chip8::new(Platform::SuperChip);
+        machine.graphics_buffer[0] = 1;
+
+        machine.SCD(2);
+        assert_eq!(machine.graphics_buffer[0], 0);
+        assert_eq!(machine.graphics_buffer[2 * 64], 1);
+
+        machine.SCR();
+        assert_eq!(machine.graphics_buffer[4 + 2 * 64], 1);
+
+        machine.SCL();
+        machine.SCL();
+        assert_eq!(
+            machine.graphics_buffer.iter().filter(|&&p| p > 0).count(),
+            0
+        );
+    }
+
+    /// SUPER-CHIP opcodes are only decoded on platforms that support them.
+    #[test]
+    fn test_schip_opcodes_need_platform() {
+        let mut machine = Chip8::new(Platform::SuperChip);
+        machine.memory[0x200] = 0x00;
+        machine.memory[0x201] = 0xFF;
+        machine.execute_opcode().unwrap();
+        assert_eq!(machine.display_size(), (128, 64));
+    }
+
+    /// The RPL user flags round trip through the registers.
+    #[test]
+    fn test_rpl_flags() {
+        let mut machine = Chip8::new(Platform::SuperChip);
+        machine.registers[0..4].copy_from_slice(&[1, 2, 3, 4]);
+        machine.SRPL(2);
+        assert_eq!(machine.rpl_flags[0..4], [1, 2, 3, 0]);
+
+        machine.registers = [0; 16];
+        machine.LRPL(3);
+        assert_eq!(machine.registers[0..4], [1, 2, 3, 0]);
+    }
+
+    /// With both planes selected, DRAW reads a second sprite for plane 2 after the first.
+    #[test]
+    fn test_xochip_planes() {
+        let mut machine = Chip8::new(Platform::XoChip);
+        machine.PLANE(3);
+        machine.index_register = 0x300;
+        machine.memory[0x300] = 0xF0;
+        machine.memory[0x301] = 0x3C;
+        machine.registers[0] = 0;
+        machine.registers[1] = 0;
+
+        machine.DRAW(0, 1, 1).unwrap();
+        assert_eq!(machine.graphics_buffer[0..8], [1, 1, 3, 3, 2, 2, 0, 0]);
+
+        // Clearing only affects the selected plane.
+        machine.PLANE(1);
+        machine.CLR();
+        assert_eq!(machine.graphics_buffer[0..8], [0, 0, 2, 2, 2, 2, 0, 0]);
+    }
+
+    /// F000 NNNN loads a 16-bit address, and skips step over all four bytes of it.
+    #[test]
+    fn test_xochip_long_load() {
+        let mut machine = Chip8::new(Platform::XoChip);
+        machine.memory[0x200..0x208]
+            .copy_from_slice(&[0x30, 0x00, 0xF0, 0x00, 0xBE, 0xEF, 0xF0, 0x00]);
+
+        // V0 == 0, so skip past F000 BEEF.
+        machine.execute_opcode().unwrap();
+        assert_eq!(machine.program_counter, 0x206);
+
+        machine.memory[0x208..0x20A].copy_from_slice(&[0x12, 0x34]);
+        machine.execute_opcode().unwrap();
+        assert_eq!(machine.index_register, 0x1234);
+        assert_eq!(machine.program_counter, 0x20A);
+    }
+
+    /// 5XY2 and 5XY3 save and load a range of registers without touching I.
+    #[test]
+    fn test_xochip_register_range() {
+        let mut machine = Chip8::new(Platform::XoChip);
+        machine.index_register = 0x8000;
+        machine.registers[2..5].copy_from_slice(&[7, 8, 9]);
+
+        machine.SAVE(4, 2).unwrap();
+        assert_eq!(machine.memory[0x8000..0x8003], [9, 8, 7]);
+        assert_eq!(machine.index_register, 0x8000);
+
+        machine.LOADR(5, 7).unwrap();
+        assert_eq!(machine.registers[5..8], [9, 8, 7]);
+    }
+
+    /// Returning with an empty stack or calling with a full one is an error, not a panic.
+    #[test]
+    fn test_stack_errors() {
+        let mut machine = Chip8::new(Platform::default());
+        machine.memory[0x200..0x202].copy_from_slice(&[0x00, 0xEE]);
+        assert_eq!(
+            machine.tick(),
+            Err(Chip8Error::StackUnderflow {
+                pc: 0x200,
+                opcode: 0x00EE
+            })
+        );
+
+        // A subroutine that calls itself forever.
+        machine.memory[0x200..0x202].copy_from_slice(&[0x22, 0x00]);
+        for _ in 0..16 {
+            assert_eq!(machine.tick(), Ok(StepOutcome::Executed));
+        }
+        assert_eq!(
+            machine.tick(),
+            Err(Chip8Error::StackOverflow {
+                pc: 0x200,
+                opcode: 0x2200
+            })
+        );
+    }
+
+    /// Unknown opcodes, and memory and program counter overruns, are reported with the PC.
+    #[test]
+    fn test_execution_errors() {
+        let mut machine = Chip8::new(Platform::default());
+        machine.memory[0x200..0x202].copy_from_slice(&[0xE0, 0x00]);
+        assert_eq!(
+            machine.tick(),
+            Err(Chip8Error::UnknownOpcode {
+                pc: 0x200,
+                opcode: 0xE000
+            })
+        );
+        assert_eq!(machine.program_counter, 0x200);
+
+        machine.index_register = 0xFFE;
+        machine.memory[0x200..0x202].copy_from_slice(&[0xF0, 0x33]);
+        assert_eq!(
+            machine.tick(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                pc: 0x200,
+                opcode: 0xF033,
+                address: 0x1000
+            })
+        );
+
+        machine.memory[0x200..0x202].copy_from_slice(&[0x1F, 0xFF]);
+        assert_eq!(machine.tick(), Ok(StepOutcome::Executed));
+        assert_eq!(
+            machine.tick(),
+            Err(Chip8Error::PcOutOfBounds {
+                pc: 0xFFF,
+                opcode: 0x1FFF
+            })
+        );
+    }
+
+    /// COSMAC VIP leaves I past the last stored register, CHIP-48 leaves it untouched.
+    #[test]
+    fn test_load_store_quirk() {
+        let mut machine = Chip8::new(Platform::CosmacVip);
+        machine.index_register = 0x300;
+        machine.STOR(3).unwrap();
+        assert_eq!(machine.index_register, 0x304);
+
+        let mut machine = Chip8::new(Platform::Chip48);
+        machine.index_register = 0x300;
+        machine.READ(3).unwrap();
+        assert_eq!(machine.index_register, 0x300);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::chip8::{Chip8, StepOutcome};
+use crate::debug::Register;
+use crate::error::Chip8Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The machine just before running an instruction, as a line of a reference trace.
+///
+/// A reference trace has a line per instruction run: the program counter, the opcode, V0 - VF and
+/// I, as hex numbers separated by whitespace, eg.
+/// `200 6005 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 000`. `0x` prefixes are optional,
+/// and blank lines and lines starting with `#` are skipped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceState {
+    pub pc: usize,
+    pub opcode: usize,
+    pub registers: [usize; 16], // V0 - VF.
+    pub index: usize,           // I.
+}
+
+impl TraceState {
+    /// The machine's state before it runs the instruction at the program counter.
+    pub fn of(machine: &Chip8) -> Self {
+        let mut registers = [0; 16];
+        for (x, value) in registers.iter_mut().enumerate() {
+            *value = machine.register(Register::V(x));
+        }
+        TraceState {
+            pc: machine.register(Register::Pc),
+            opcode: machine.next_opcode().unwrap_or(0),
+            registers,
+            index: machine.register(Register::I),
+        }
+    }
+
+    /// Read a reference trace. Errors give the line number.
+    pub fn parse_trace(text: &str) -> Result<Vec<TraceState>, String> {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(n, line)| line.parse().map_err(|e| format!("line {}: {}", n + 1, e)))
+            .collect()
+    }
+
+    /// Two states in columns, in the style of `Chip8::dump_state`, with the rows that differ
+    /// marked.
+    pub fn side_by_side(&self, other: &TraceState, titles: (&str, &str)) -> String {
+        let mut rows = vec![
+            (
+                "PC".to_string(),
+                format!("{:x}", self.pc),
+                format!("{:x}", other.pc),
+            ),
+            (
+                "Opcode".to_string(),
+                format!("{:#X}", self.opcode),
+                format!("{:#X}", other.opcode),
+            ),
+            (
+                "I".to_string(),
+                format!("{:x}", self.index),
+                format!("{:x}", other.index),
+            ),
+        ];
+        for x in 0..16 {
+            rows.push((
+                format!("V{:X}", x),
+                format!("{:x}", self.registers[x]),
+                format!("{:x}", other.registers[x]),
+            ));
+        }
+
+        let mut text = format!("{:<9}{:<12}{}\n", "", titles.0, titles.1);
+        for (name, left, right) in rows {
+            let marker = if left != right { "  <-" } else { "" };
+            let row = format!("{:<9}{:<12}{:<12}", name + ":", left, right);
+            text += &format!("{}{}\n", row.trim_end(), marker);
+        }
+        text
+    }
+}
+
+impl FromStr for TraceState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numbers = s
+            .split_whitespace()
+            .map(|word| {
+                let hex = word.strip_prefix("0x").unwrap_or(word);
+                usize::from_str_radix(hex, 16)
+                    .map_err(|_| format!("'{}' is not a hex number", word))
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+
+        if numbers.len() != 19 {
+            return Err(format!(
+                "Expected 19 numbers (PC, opcode, V0-VF, I), found {}",
+                numbers.len()
+            ));
+        }
+        let mut registers = [0; 16];
+        registers.copy_from_slice(&numbers[2..18]);
+        Ok(TraceState {
+            pc: numbers[0],
+            opcode: numbers[1],
+            registers,
+            index: numbers[18],
+        })
+    }
+}
+
+impl fmt::Display for TraceState {
+    /// A line of a reference trace.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:03X} {:04X}", self.pc, self.opcode)?;
+        for value in &self.registers {
+            write!(f, " {:02X}", value)?;
+        }
+        write!(f, " {:03X}", self.index)
+    }
+}
+
+/// How a run compared with a reference trace.
+#[allow(clippy::large_enum_variant)] // Made once per comparison.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceComparison {
+    Matched(usize), // Every step of the reference matched.
+    Diverged {
+        step: usize, // Counted from 1, the line of the reference without comments.
+        expected: TraceState,
+        actual: TraceState,
+    },
+    Stopped {
+        step: usize,
+        outcome: StepOutcome, // Waiting for a key, or exited, before the reference ended.
+    },
+}
+
+impl fmt::Display for TraceComparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceComparison::Matched(steps) => write!(f, "All {} steps match.", steps),
+            TraceComparison::Diverged {
+                step,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Diverged at step {}.\n{}",
+                step,
+                expected.side_by_side(actual, ("Reference", "Emulator"))
+            ),
+            TraceComparison::Stopped { step, outcome } => {
+                let reason = match outcome {
+                    StepOutcome::WaitingForInput => "waiting for a key",
+                    _ => "exited",
+                };
+                write!(f, "The ROM stopped at step {}, {}.", step, reason)
+            }
+        }
+    }
+}
+
+/// Run the machine an instruction at a time with `tick`, comparing it with the reference before
+/// each one. Stops at the first difference. Timers and waiting for the display advance every
+/// `instructions_per_frame` ticks.
+pub fn compare_trace(
+    machine: &mut Chip8,
+    reference: &[TraceState],
+    instructions_per_frame: usize,
+) -> Result<TraceComparison, Chip8Error> {
+    let mut ticks = 0;
+    for (i, expected) in reference.iter().enumerate() {
+        let step = i + 1;
+        let actual = TraceState::of(machine);
+        if actual != *expected {
+            return Ok(TraceComparison::Diverged {
+                step,
+                expected: expected.clone(),
+                actual,
+            });
+        }
+
+        // Tick until the instruction is run, through any waits for the display.
+        loop {
+            let outcome = machine.tick()?;
+            ticks += 1;
+            if ticks % instructions_per_frame.max(1) == 0 {
+                machine.end_frame();
+            }
+
+            match outcome {
+                StepOutcome::Executed | StepOutcome::Watchpoint => break,
+                StepOutcome::WaitingForVblank => (),
+                StepOutcome::WaitingForInput | StepOutcome::Exited => {
+                    return Ok(TraceComparison::Stopped { step, outcome })
+                }
+            }
+        }
+    }
+    Ok(TraceComparison::Matched(reference.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Platform;
+
+    // 0x200 LOAD V0 0x05, 0x202 LOADI 0x300, 0x204 ADD V0 1, 0x206 JUMP 0x206.
+    const PROGRAM: [u8; 8] = [0x60, 0x05, 0xA3, 0x00, 0x70, 0x01, 0x12, 0x06];
+
+    const REFERENCE: &str = "
+        # pc opcode v0 - vf i
+        200 6005 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 000
+        0x202 0xA300 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 000
+        204 7001 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 300
+        206 1206 06 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 300
+    ";
+
+    /// Reference traces are read, skipping comments, and errors give their line.
+    #[test]
+    fn test_parse_trace() {
+        let trace = TraceState::parse_trace(REFERENCE).unwrap();
+        assert_eq!(trace.len(), 4);
+        assert_eq!(trace[1].pc, 0x202);
+        assert_eq!(trace[1].opcode, 0xA300);
+        assert_eq!(trace[2].registers[0], 5);
+        assert_eq!(trace[2].index, 0x300);
+        assert_eq!(
+            trace[0].to_string(),
+            "200 6005 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 000"
+        );
+
+        assert_eq!(
+            TraceState::parse_trace("\n200 6000 00"),
+            Err("line 2: Expected 19 numbers (PC, opcode, V0-VF, I), found 3".to_string())
+        );
+    }
+
+    /// A matching run compares every step. One that differs stops there, showing both states.
+    #[test]
+    fn test_compare_trace() {
+        let mut machine = Chip8::new(Platform::default());
+        machine.load_program(&PROGRAM).unwrap();
+        let reference = TraceState::parse_trace(REFERENCE).unwrap();
+        assert_eq!(
+            compare_trace(&mut machine, &reference, 10),
+            Ok(TraceComparison::Matched(4))
+        );
+
+        let mut machine = Chip8::new(Platform::default());
+        machine.load_program(&PROGRAM).unwrap();
+        let reference =
+            TraceState::parse_trace(&REFERENCE.replace("206 1206 06", "206 1206 07")).unwrap();
+        let result = compare_trace(&mut machine, &reference, 10).unwrap();
+
+        match &result {
+            TraceComparison::Diverged { step, .. } => assert_eq!(*step, 4),
+            _ => panic!("Expected a divergence, got {:?}", result),
+        }
+        let text = result.to_string();
+        assert!(text.starts_with("Diverged at step 4.\n         Reference   Emulator\n"));
+        assert!(text.contains("\nV0:      7           6  <-\n"));
+        assert!(text.contains("\nV1:      0           0\n"));
+    }
+}
@@ -0,0 +1,252 @@
+use crate::chip8::Chip8;
+use crate::instruction::Instruction;
+use crate::png;
+use std::fmt::Write;
+use std::ops::Range;
+
+/// Which bytes of memory were run as code and which were read as data. See
+/// `Chip8::start_coverage`.
+#[derive(Clone, Debug)]
+pub struct Coverage {
+    pub rom: Range<usize>,  // Addresses the ROM was loaded to.
+    pub executed: Vec<u32>, // Times each byte was run as part of an instruction.
+    pub read: Vec<u32>,     // Times each byte was read by DRAW, READ and the like.
+}
+
+/// What a stretch of memory was used for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Usage {
+    Unused,
+    Code,
+    Data,
+    CodeAndData, // Read as data and run, eg. a sprite drawn from its own code.
+}
+
+impl Coverage {
+    /// The addresses the heatmap covers: all of the original Chip8's program memory.
+    pub const HEATMAP_RANGE: Range<usize> = 0x200..0x1000;
+    const HEATMAP_COLUMNS: usize = 64; // Bytes per row of the heatmap.
+    const HEATMAP_SCALE: usize = 4; // Pixels per byte, across and down.
+
+    fn new(rom: Range<usize>, memory_size: usize) -> Self {
+        Coverage {
+            rom,
+            executed: vec![0; memory_size],
+            read: vec![0; memory_size],
+        }
+    }
+
+    pub fn usage(&self, address: usize) -> Usage {
+        match (self.executed[address] > 0, self.read[address] > 0) {
+            (false, false) => Usage::Unused,
+            (true, false) => Usage::Code,
+            (false, true) => Usage::Data,
+            (true, true) => Usage::CodeAndData,
+        }
+    }
+
+    /// The ROM split into runs of addresses used the same way, in order.
+    pub fn ranges(&self) -> Vec<(Range<usize>, Usage)> {
+        let mut ranges: Vec<(Range<usize>, Usage)> = Vec::new();
+        for address in self.rom.clone() {
+            let usage = self.usage(address);
+            match ranges.last_mut() {
+                Some((range, last)) if *last == usage => range.end = address + 1,
+                _ => ranges.push((address..address + 1, usage)),
+            }
+        }
+        ranges
+    }
+
+    /// A summary of how much of the ROM was used, then each run of addresses and its usage.
+    pub fn report(&self) -> String {
+        let count = |usage: Usage| self.rom.clone().filter(|&a| self.usage(a) == usage).count();
+        let size = self.rom.len();
+        let percent = |n: usize| 100.0 * n as f64 / size.max(1) as f64;
+        let mut text = String::new();
+
+        writeln!(
+            text,
+            "ROM: {} bytes, {:#05X}-{:#05X}.",
+            size,
+            self.rom.start,
+            self.rom.end.max(self.rom.start + 1) - 1
+        )
+        .unwrap();
+        for &(name, usage) in &[
+            ("Code", Usage::Code),
+            ("Data", Usage::Data),
+            ("Code and data", Usage::CodeAndData),
+            ("Never reached", Usage::Unused),
+        ] {
+            let n = count(usage);
+            writeln!(text, "{:<15}{:>6} bytes ({:.1}%)", name, n, percent(n)).unwrap();
+        }
+
+        writeln!(text).unwrap();
+        for (range, usage) in self.ranges() {
+            let name = match usage {
+                Usage::Unused => "never reached",
+                Usage::Code => "code",
+                Usage::Data => "data",
+                Usage::CodeAndData => "code and data",
+            };
+            writeln!(
+                text,
+                "{:#05X}-{:#05X}  {:>5} bytes  {}",
+                range.start,
+                range.end - 1,
+                range.len(),
+                name
+            )
+            .unwrap();
+        }
+        text
+    }
+
+    /// A PNG of `HEATMAP_RANGE`, 64 bytes to a row from the top left. Code is green and data is
+    /// blue, brighter the more it was used. Bytes in the ROM that were never reached are red, and
+    /// memory past the ROM is black.
+    pub fn heatmap(&self) -> Vec<u8> {
+        let range = Coverage::HEATMAP_RANGE;
+        let (columns, scale) = (Coverage::HEATMAP_COLUMNS, Coverage::HEATMAP_SCALE);
+        let rows = range.len().div_ceil(columns);
+        let (width, height) = (columns * scale, rows * scale);
+
+        // Brightness grows with the log of the count, so a loop run thousands of times doesn't
+        // wash out code run once.
+        let busiest = range
+            .clone()
+            .map(|a| self.count(&self.executed, a).max(self.count(&self.read, a)))
+            .max()
+            .unwrap_or(0);
+        let brightness = |n: u32| match n {
+            0 => 0,
+            n => {
+                let scaled = ((n as f64).ln_1p() / (busiest as f64).ln_1p()) * 191.0;
+                64 + scaled as u8
+            }
+        };
+
+        let mut pixels = vec![0; width * height * 3];
+        for (offset, address) in range.enumerate() {
+            let (code, data) = (
+                self.count(&self.executed, address),
+                self.count(&self.read, address),
+            );
+            let colour = if code == 0 && data == 0 && self.rom.contains(&address) {
+                [96, 0, 0]
+            } else {
+                [0, brightness(code), brightness(data)]
+            };
+
+            let (x, y) = ((offset % columns) * scale, (offset / columns) * scale);
+            for row in y..y + scale {
+                for column in x..x + scale {
+                    let pixel = (row * width + column) * 3;
+                    pixels[pixel..pixel + 3].copy_from_slice(&colour);
+                }
+            }
+        }
+
+        png::encode(width, height, &pixels)
+    }
+
+    /// A count at an address, or 0 past the end of memory.
+    fn count(&self, counts: &[u32], address: usize) -> u32 {
+        counts.get(address).copied().unwrap_or(0)
+    }
+
+    fn mark(counts: &mut [u32], start: usize, len: usize) {
+        let end = (start + len).min(counts.len());
+        for count in &mut counts[start.min(end)..end] {
+            *count = count.saturating_add(1);
+        }
+    }
+}
+
+/// Coverage. Each instruction run marks its bytes as code, and each opcode reading memory marks
+/// the bytes it read as data. LDSPR only points I at the font, so the font is marked by the DRAW
+/// that follows it.
+impl Chip8 {
+    /// Record which bytes are used as code and data from now on, starting from nothing.
+    pub fn start_coverage(&mut self) {
+        let rom = Chip8::ADDRESS_ROM..Chip8::ADDRESS_ROM + self.rom_size;
+        self.coverage = Some(Coverage::new(rom, self.memory.len()));
+    }
+
+    /// Stop recording, returning what was recorded.
+    pub fn stop_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// What has been recorded so far, if recording.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Mark the instruction just run at `pc` as code.
+    pub(crate) fn cover_instruction(&mut self, pc: usize) {
+        if let Some(coverage) = self.coverage.as_mut() {
+            let size = Instruction::decode_for(self.last_opcode as u16, self.platform)
+                .map(|instruction| instruction.size())
+                .unwrap_or(Chip8::OPCODE_SIZE);
+            Coverage::mark(&mut coverage.executed, pc, size);
+        }
+    }
+
+    /// Mark `len` bytes from `start` as read as data.
+    pub(crate) fn cover_read(&mut self, start: usize, len: usize) {
+        if let Some(coverage) = self.coverage.as_mut() {
+            Coverage::mark(&mut coverage.read, start, len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Platform;
+
+    // 0x200 LOADI 0x20A, 0x202 DRAW V0 V0 2, 0x204 READ V1, 0x206 JUMP 0x206,
+    // 0x208 (never reached), 0x20A sprite, 0x20C spare.
+    const PROGRAM: [u8; 14] = [
+        0xA2, 0x0A, 0xD0, 0x02, 0xF1, 0x65, 0x12, 0x06, 0x00, 0xE0, 0xFF, 0x81, 0x00, 0x00,
+    ];
+
+    /// Instructions run are code, bytes read by DRAW and READ are data, and the rest is unused.
+    #[test]
+    fn test_coverage() {
+        let mut machine = Chip8::new(Platform::default());
+        machine.load_program(&PROGRAM).unwrap();
+        machine.start_coverage();
+        for _ in 0..6 {
+            machine.tick().unwrap();
+            machine.end_frame(); // DRAW waits for the display on the VIP.
+        }
+
+        let coverage = machine.coverage().unwrap();
+        assert_eq!(coverage.rom, 0x200..0x20E);
+        assert_eq!(coverage.executed[0x206], 3);
+        assert_eq!(coverage.read[0x20A], 2);
+        assert_eq!(
+            coverage.ranges(),
+            vec![
+                (0x200..0x208, Usage::Code),
+                (0x208..0x20A, Usage::Unused),
+                (0x20A..0x20C, Usage::Data),
+                (0x20C..0x20E, Usage::Unused),
+            ]
+        );
+
+        let report = coverage.report();
+        assert!(report.starts_with("ROM: 14 bytes, 0x200-0x20D.\nCode                8 bytes"));
+        assert!(report.contains("\n0x208-0x209      2 bytes  never reached\n"));
+
+        let heatmap = coverage.heatmap();
+        assert!(heatmap.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        assert!(machine.stop_coverage().is_some());
+        assert!(machine.coverage().is_none());
+    }
+}
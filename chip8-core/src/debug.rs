@@ -0,0 +1,159 @@
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::quirks::Platform;
+use std::fmt;
+use std::str::FromStr;
+
+/// A register a debugger can read or change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    V(usize), // V0 - VF.
+    I,
+    Pc,
+    Sp,
+    DelayTimer,
+    SoundTimer,
+}
+
+impl Register {
+    /// The largest value the register can hold.
+    pub fn max_value(self) -> usize {
+        match self {
+            Register::V(_) | Register::DelayTimer | Register::SoundTimer => 0xFF,
+            Register::I | Register::Pc => 0xFFFF,
+            Register::Sp => 16,
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    /// Registers are named v0-vf, i, pc, sp, dt (delay timer) and st (sound timer).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        match name.as_str() {
+            "i" => Ok(Register::I),
+            "pc" => Ok(Register::Pc),
+            "sp" => Ok(Register::Sp),
+            "dt" => Ok(Register::DelayTimer),
+            "st" => Ok(Register::SoundTimer),
+            _ if name.len() == 2 && name.starts_with('v') => usize::from_str_radix(&name[1..], 16)
+                .map(Register::V)
+                .map_err(|_| format!("Unknown register '{}'.", s)),
+            _ => Err(format!(
+                "Unknown register '{}'. Expected one of: v0-vf, i, pc, sp, dt, st",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+            Register::Sp => write!(f, "SP"),
+            Register::DelayTimer => write!(f, "DT"),
+            Register::SoundTimer => write!(f, "ST"),
+        }
+    }
+}
+
+/// Inspecting and changing the machine, for debuggers.
+impl Chip8 {
+    pub fn register(&self, register: Register) -> usize {
+        match register {
+            Register::V(x) => self.registers[x & 0xF],
+            Register::I => self.index_register,
+            Register::Pc => self.program_counter,
+            Register::Sp => self.stack_pointer,
+            Register::DelayTimer => self.delay_timer,
+            Register::SoundTimer => self.sound_timer,
+        }
+    }
+
+    /// Change a register. Values too large for it are cut down to its largest value.
+    pub fn set_register(&mut self, register: Register, value: usize) {
+        let value = value.min(register.max_value());
+        match register {
+            Register::V(x) => self.registers[x & 0xF] = value,
+            Register::I => self.index_register = value,
+            Register::Pc => self.program_counter = value,
+            Register::Sp => self.stack_pointer = value,
+            Register::DelayTimer => self.delay_timer = value,
+            Register::SoundTimer => self.sound_timer = value,
+        }
+    }
+
+    /// All of memory, a byte per element.
+    pub fn memory(&self) -> &[usize] {
+        &self.memory
+    }
+
+    /// Overwrite memory from `address` onwards.
+    pub fn write_memory(&mut self, address: usize, bytes: &[u8]) -> Result<(), Chip8Error> {
+        self.check_memory(address, bytes.len())?;
+        for (i, &b) in bytes.iter().enumerate() {
+            self.memory[address + i] = b as usize;
+        }
+        Ok(())
+    }
+
+    /// The return addresses of the subroutines being run, outermost first.
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.stack_pointer]
+    }
+
+    /// How many cycles have been run.
+    pub fn cycle(&self) -> usize {
+        self.cycle
+    }
+
+    /// The interpreter being emulated.
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// The opcode at the program counter, to be run next.
+    pub fn next_opcode(&self) -> Option<usize> {
+        self.get_opcode().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Registers are found by name, and are changed within their size.
+    #[test]
+    fn test_registers() {
+        let mut machine = Chip8::new(Platform::default());
+
+        assert_eq!("vA".parse(), Ok(Register::V(0xA)));
+        assert_eq!("dt".parse(), Ok(Register::DelayTimer));
+        assert!("vg".parse::<Register>().is_err());
+        assert!("x".parse::<Register>().is_err());
+
+        machine.set_register(Register::V(0xA), 0x2A);
+        machine.set_register(Register::I, 0x300);
+        machine.set_register(Register::SoundTimer, 0x1234);
+        assert_eq!(machine.register(Register::V(0xA)), 0x2A);
+        assert_eq!(machine.register(Register::I), 0x300);
+        assert_eq!(machine.register(Register::SoundTimer), 0xFF);
+        assert_eq!(Register::V(0xA).to_string(), "VA");
+    }
+
+    /// Memory is written within bounds only.
+    #[test]
+    fn test_write_memory() {
+        let mut machine = Chip8::new(Platform::default());
+
+        machine.write_memory(0x300, &[1, 2, 3]).unwrap();
+        assert_eq!(&machine.memory()[0x300..0x303], &[1, 2, 3]);
+        assert!(machine.write_memory(0xFFF, &[1, 2]).is_err());
+        assert!(machine.write_memory(usize::MAX, &[1]).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::instruction::Instruction;
+use crate::quirks::Platform;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// A line of a disassembly: an instruction, or a byte of data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisassemblyLine {
+    pub address: usize,
+    pub bytes: Vec<u8>, // The instruction's 2 (or 4) bytes, or the data byte.
+    pub text: String,   // The mnemonic and operands, or `.byte` and the value.
+    pub is_code: bool,
+}
+
+impl fmt::Display for DisassemblyLine {
+    /// The address, bytes and text. Data is followed by its bits, as it's often a sprite row.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex: String = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "{:#05X}  {:<8}  {}", self.address, hex, self.text)?;
+        if !self.is_code {
+            let bits: String = (0..8)
+                .map(|bit| {
+                    if self.bytes[0] & 0x80 >> bit > 0 {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            write!(f, "  ; {}", bits)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where execution can go after an instruction.
+enum Flow {
+    Next,        // On to the following instruction.
+    Skip,        // The following instruction, or the one after it.
+    Jump(usize), // Only to the address.
+    Call(usize), // To the address, and back to the following instruction.
+    Stop,        // Nowhere that can be known. RTS, EXIT, JUMPI or SYS.
+}
+
+/// Disassemble `bytes`, which are loaded at `origin`.
+///
+/// Code is told apart from data by tracing the program from its `entries` (usually just 0x200):
+/// following jumps, calls and both sides of skips until each path returns, jumps somewhere
+/// unknowable (JUMPI) or meets an unknown opcode. Everything not reached is data, such as sprites.
+pub fn disassemble(
+    bytes: &[u8],
+    origin: usize,
+    entries: &[usize],
+    platform: Platform,
+) -> Vec<DisassemblyLine> {
+    let starts = trace(bytes, origin, entries, platform);
+
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let address = origin + offset;
+        let decoded = if starts.contains(&address) {
+            decode_at(bytes, offset, platform)
+        } else {
+            None
+        };
+
+        let line = match decoded {
+            Some((instruction, text)) => DisassemblyLine {
+                address,
+                bytes: bytes[offset..offset + instruction.size()].to_vec(),
+                text,
+                is_code: true,
+            },
+            None => DisassemblyLine {
+                address,
+                bytes: vec![bytes[offset]],
+                text: format!(".byte {:#04X}", bytes[offset]),
+                is_code: false,
+            },
+        };
+
+        offset += line.bytes.len();
+        lines.push(line);
+    }
+    lines
+}
+
+/// The addresses of every instruction reachable from the entry points.
+fn trace(bytes: &[u8], origin: usize, entries: &[usize], platform: Platform) -> BTreeSet<usize> {
+    let mut starts = BTreeSet::new();
+    let mut is_code = vec![false; bytes.len()];
+    let mut pending: Vec<usize> = entries.to_vec();
+
+    while let Some(mut address) = pending.pop() {
+        // Follow a path until it ends, or meets code that's already been traced.
+        while address >= origin && !starts.contains(&address) {
+            let offset = address - origin;
+            let instruction = match decode_at(bytes, offset, platform) {
+                Some((i, _)) if !is_code[offset..offset + i.size()].contains(&true) => i,
+                _ => break,
+            };
+            let size = instruction.size();
+
+            starts.insert(address);
+            is_code[offset..offset + size]
+                .iter_mut()
+                .for_each(|c| *c = true);
+
+            let next = address + size;
+            match flow(instruction) {
+                Flow::Next => address = next,
+                Flow::Skip => {
+                    let skipped =
+                        decode_at(bytes, next - origin, platform).map_or(2, |(i, _)| i.size());
+                    pending.push(next + skipped);
+                    address = next;
+                }
+                Flow::Jump(target) => {
+                    pending.push(target);
+                    break;
+                }
+                Flow::Call(target) => {
+                    pending.push(target);
+                    address = next;
+                }
+                Flow::Stop => break,
+            }
+        }
+    }
+
+    starts
+}
+
+/// The big-endian 16-bit word at `offset`, if there are two bytes there.
+fn opcode_at(bytes: &[u8], offset: usize) -> Option<usize> {
+    match bytes.get(offset..offset + 2) {
+        Some(&[high, low]) => Some((high as usize) << 8 | low as usize),
+        _ => None,
+    }
+}
+
+/// The instruction at `offset` and its text. XO-CHIP's LOADIL is shown with the address in the
+/// word after it, so it needs two words.
+fn decode_at(bytes: &[u8], offset: usize, platform: Platform) -> Option<(Instruction, String)> {
+    let opcode = opcode_at(bytes, offset)?;
+    let instruction = Instruction::decode_for(opcode as u16, platform).ok()?;
+    let text = match instruction {
+        Instruction::LOADIL => format!("LOADIL {:#06X}", opcode_at(bytes, offset + 2)?),
+        _ => instruction.to_string(),
+    };
+    Some((instruction, text))
+}
+
+/// Where execution can go after an instruction.
+fn flow(instruction: Instruction) -> Flow {
+    use Instruction::*;
+
+    match instruction {
+        JUMP(address) => Flow::Jump(address),
+        CALL(address) => Flow::Call(address),
+        SKE(..) | SKNE(..) | SKRE(..) | SKRNE(..) | SKPR(_) | SKUP(_) => Flow::Skip,
+        RTS | EXIT | JUMPI(_) | SYS(_) => Flow::Stop,
+        _ => Flow::Next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(lines: &[DisassemblyLine]) -> Vec<(usize, &str, bool)> {
+        lines
+            .iter()
+            .map(|l| (l.address, l.text.as_str(), l.is_code))
+            .collect()
+    }
+
+    /// Sprite data after the code is left as data, and both sides of skips and calls are traced.
+    #[test]
+    fn test_disassemble() {
+        let rom = [
+            0xA2, 0x0C, // 0x200 LOADI 0x20C
+            0x30, 0x01, // 0x202 SKE V0, 0x01
+            0x22, 0x0A, // 0x204 CALL 0x20A
+            0xD0, 0x12, // 0x206 DRAW V0, V1, 2
+            0x12, 0x08, // 0x208 JUMP 0x208
+            0x00, 0xEE, // 0x20A RTS
+            0xF0, 0x90, // 0x20C Sprite.
+        ];
+        let lines = disassemble(&rom, 0x200, &[0x200], Platform::default());
+
+        assert_eq!(
+            texts(&lines),
+            vec![
+                (0x200, "LOADI 0x20C", true),
+                (0x202, "SKE V0, 0x01", true),
+                (0x204, "CALL 0x20A", true),
+                (0x206, "DRAW V0, V1, 2", true),
+                (0x208, "JUMP 0x208", true),
+                (0x20A, "RTS", true),
+                (0x20C, ".byte 0xF0", false),
+                (0x20D, ".byte 0x90", false),
+            ]
+        );
+        assert_eq!(
+            lines[6].to_string(),
+            "0x20C  F0        .byte 0xF0  ; ####...."
+        );
+    }
+
+    /// Instructions depend on the platform, and XO-CHIP's LOADIL is 4 bytes.
+    #[test]
+    fn test_disassemble_platforms() {
+        let rom = [0xF0, 0x00, 0x12, 0x34, 0x00, 0xFF];
+
+        let lines = disassemble(&rom, 0x200, &[0x200], Platform::XoChip);
+        assert_eq!(
+            texts(&lines),
+            vec![(0x200, "LOADIL 0x1234", true), (0x204, "HIGH", true)]
+        );
+
+        let lines = disassemble(&rom, 0x200, &[0x200], Platform::CosmacVip);
+        assert_eq!(lines.iter().filter(|l| l.is_code).count(), 0);
+    }
+}
@@ -0,0 +1,126 @@
+use std::error::Error;
+use std::fmt;
+
+/// Reasons the Chip8 can fail to execute an opcode.
+/// Each carries the program counter and opcode of the instruction that failed. The machine is left
+/// with the program counter pointing at that instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Chip8Error {
+    UnknownOpcode {
+        pc: usize,
+        opcode: usize,
+    },
+    StackOverflow {
+        pc: usize,
+        opcode: usize,
+    },
+    StackUnderflow {
+        pc: usize,
+        opcode: usize,
+    },
+    MemoryOutOfBounds {
+        pc: usize,
+        opcode: usize,
+        address: usize, // First address that could not be accessed.
+    },
+    PcOutOfBounds {
+        pc: usize,
+        opcode: usize, // The last opcode run, which moved the program counter out of bounds.
+    },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "Unknown opcode {:#06X} at {:#05X}", opcode, pc)
+            }
+            Chip8Error::StackOverflow { pc, opcode } => write!(
+                f,
+                "Stack overflow calling subroutine with {:#06X} at {:#05X}",
+                opcode, pc
+            ),
+            Chip8Error::StackUnderflow { pc, opcode } => write!(
+                f,
+                "Stack underflow returning from subroutine with {:#06X} at {:#05X}",
+                opcode, pc
+            ),
+            Chip8Error::MemoryOutOfBounds {
+                pc,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "Memory address {:#X} out of bounds for {:#06X} at {:#05X}",
+                address, opcode, pc
+            ),
+            Chip8Error::PcOutOfBounds { pc, opcode } => write!(
+                f,
+                "Program counter {:#X} out of bounds after {:#06X}",
+                pc, opcode
+            ),
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+/// Reasons a save state could not be restored.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotError {
+    NotASnapshot,            // The data doesn't start with the snapshot magic bytes.
+    UnsupportedVersion(u16), // Written by a newer (or unknown) version of the format.
+    RomMismatch,             // Saved while running a different ROM.
+    Truncated,               // The data ended early.
+    Invalid(&'static str),   // A field has a value the machine can't hold.
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "Not a save state file"),
+            SnapshotError::UnsupportedVersion(v) => {
+                write!(f, "Save state version {} is not supported", v)
+            }
+            SnapshotError::RomMismatch => write!(f, "Save state is for a different ROM"),
+            SnapshotError::Truncated => write!(f, "Save state is truncated"),
+            SnapshotError::Invalid(field) => write!(f, "Save state has an invalid {}", field),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+/// An opcode that isn't an instruction (on the platform it was decoded for).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecodeError {
+    pub opcode: usize,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown opcode {:#06X}", self.opcode)
+    }
+}
+
+impl Error for DecodeError {}
+
+/// A mistake in assembly source, and where it is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,   // Counted from 1.
+    pub column: usize, // Counted from 1.
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for AssembleError {}
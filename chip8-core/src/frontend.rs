@@ -0,0 +1,121 @@
+use crate::quirks::Platform;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// The options every frontend takes: `<rom-file> [--platform <name>] [--ipf <n>]`.
+#[derive(Debug, PartialEq)]
+pub struct RomOptions {
+    pub path: String,
+    pub platform: Platform,
+    pub instructions_per_frame: usize, // --ipf, or the platform's default.
+}
+
+impl RomOptions {
+    /// Parse the command line, `args[0]` being the program and `args[1]` the ROM. Returns the
+    /// flags other than --platform and --ipf, with their values, for the frontend's own options.
+    pub fn parse(args: &[String]) -> Result<(Self, Vec<(String, String)>), String> {
+        let path = args.get(1).cloned().ok_or("No ROM file given.")?;
+        let mut platform = Platform::default();
+        let mut instructions_per_frame = None;
+        let mut others = Vec::new();
+
+        let mut args = args.iter().skip(2);
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or(format!("{} requires a value.", flag))?;
+
+            match flag.as_str() {
+                "--platform" => platform = value.parse()?,
+                "--ipf" => instructions_per_frame = Some(parse_number(flag, value)?),
+                _ => others.push((flag.clone(), value.clone())),
+            }
+        }
+
+        let options = Self {
+            path,
+            platform,
+            instructions_per_frame: instructions_per_frame
+                .unwrap_or_else(|| platform.instructions_per_frame()),
+        };
+        Ok((options, others))
+    }
+}
+
+/// A flag's value as a number.
+pub fn parse_number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, not '{}'.", flag, value))
+}
+
+/// Keeps a frontend's loop at 60 frames a second. Frames are scheduled against the wall clock,
+/// so time lost to a slow frame or oversleeping is made up on the next one.
+pub struct FramePacer {
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    pub const FRAME_RATE: u32 = 60; // Frames per second. The Chip8 timers tick once per frame.
+
+    /// Pace frames from now.
+    pub fn start() -> Self {
+        Self {
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Sleep until the next frame is due. If more than a frame behind (eg. the process was
+    /// suspended), start over from now rather than rushing to catch up.
+    pub fn wait(&mut self) {
+        let frame_duration = Duration::from_secs(1) / FramePacer::FRAME_RATE;
+        self.next_frame += frame_duration;
+        let now = Instant::now();
+        if self.next_frame > now {
+            sleep(self.next_frame - now);
+        } else if now - self.next_frame > frame_duration {
+            self.next_frame = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The ROM comes first, --ipf defaults by platform, and other flags are passed back in order.
+    #[test]
+    fn test_parse_rom_options() {
+        let args = |line: &str| -> Vec<String> { line.split(' ').map(String::from).collect() };
+
+        let (options, others) =
+            RomOptions::parse(&args("chip8 game.ch8 --seed 3 --platform schip --trace t")).unwrap();
+        assert_eq!(
+            options,
+            RomOptions {
+                path: "game.ch8".to_string(),
+                platform: Platform::SuperChip,
+                instructions_per_frame: 30,
+            }
+        );
+        assert_eq!(
+            others,
+            vec![
+                ("--seed".to_string(), "3".to_string()),
+                ("--trace".to_string(), "t".to_string())
+            ]
+        );
+
+        let (options, _) = RomOptions::parse(&args("chip8 game.ch8 --ipf 7")).unwrap();
+        assert_eq!(options.instructions_per_frame, 7);
+
+        assert!(RomOptions::parse(&args("chip8")).is_err());
+        assert_eq!(
+            RomOptions::parse(&args("chip8 game.ch8 --ipf")).unwrap_err(),
+            "--ipf requires a value."
+        );
+        assert_eq!(
+            RomOptions::parse(&args("chip8 game.ch8 --ipf fast")).unwrap_err(),
+            "--ipf expects a number, not 'fast'."
+        );
+    }
+}
@@ -0,0 +1,431 @@
+use crate::error::DecodeError;
+use crate::quirks::Platform;
+use std::fmt;
+
+/// A decoded opcode. Each is named after the `Chip8` method that runs it, with its operands:
+/// registers (X, Y) as their number, then constants (N, NN) and addresses (NNN).
+///
+/// XO-CHIP's `LOADIL` is followed by a second word, the address to load, which isn't part of the
+/// instruction. See `size`.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    SCD(usize), // SUPER-CHIP.
+    SCU(usize), // XO-CHIP.
+    CLR,
+    RTS,
+    SCR,  // SUPER-CHIP.
+    SCL,  // SUPER-CHIP.
+    EXIT, // SUPER-CHIP.
+    LOW,  // SUPER-CHIP.
+    HIGH, // SUPER-CHIP.
+    SYS(usize),
+    JUMP(usize),
+    CALL(usize),
+    SKE(usize, usize),
+    SKNE(usize, usize),
+    SKRE(usize, usize),
+    SAVE(usize, usize),  // XO-CHIP.
+    LOADR(usize, usize), // XO-CHIP.
+    LOAD(usize, usize),
+    ADD(usize, usize),
+    MOVE(usize, usize),
+    OR(usize, usize),
+    AND(usize, usize),
+    XOR(usize, usize),
+    ADDR(usize, usize),
+    SUB(usize, usize),
+    SHR(usize, usize),
+    SUBN(usize, usize),
+    SHL(usize, usize),
+    SKRNE(usize, usize),
+    LOADI(usize),
+    JUMPI(usize),
+    RAND(usize, usize),
+    DRAWL(usize, usize), // SUPER-CHIP.
+    DRAW(usize, usize, usize),
+    SKPR(usize),
+    SKUP(usize),
+    LOADIL,       // XO-CHIP.
+    PLANE(usize), // XO-CHIP.
+    AUDIO,        // XO-CHIP.
+    MOVED(usize),
+    KEYD(usize),
+    LOADD(usize),
+    LOADS(usize),
+    ADDI(usize),
+    LDSPR(usize),
+    LDHSPR(usize), // SUPER-CHIP.
+    BCD(usize),
+    PITCH(usize), // XO-CHIP.
+    STOR(usize),
+    READ(usize),
+    SRPL(usize), // SUPER-CHIP.
+    LRPL(usize), // SUPER-CHIP.
+}
+
+impl Instruction {
+    /// Decode an opcode as the instruction it is on any platform. Opcodes that are different
+    /// instructions on different platforms decode as the SUPER-CHIP or XO-CHIP one, eg. `D010` is
+    /// `DRAWL`. See `decode_for`.
+    pub fn decode(opcode: u16) -> Result<Self, DecodeError> {
+        use Instruction::*;
+
+        let opcode = opcode as usize;
+        let a = (opcode & 0xF000) >> 12; // 4-bit opcode identifier.
+        let x = (opcode & 0x0F00) >> 8; // 4-bit register identifier.
+        let y = (opcode & 0x00F0) >> 4; // 4-bit register identifier.
+        let n = opcode & 0x000F; // 4-bit constant.
+        let nn = opcode & 0x00FF; // 8-bit constant.
+        let nnn = opcode & 0x0FFF; // 12-bit address.
+
+        // The order of these match branches are important.
+        // Some opcodes are more specific than others.
+        let instruction = match (a, x, y, n) {
+            (0, 0, 0xC, _) => SCD(n),
+            (0, 0, 0xD, _) => SCU(n),
+            (0, 0, 0xE, 0) => CLR,
+            (0, 0, 0xE, 0xE) => RTS,
+            (0, 0, 0xF, 0xB) => SCR,
+            (0, 0, 0xF, 0xC) => SCL,
+            (0, 0, 0xF, 0xD) => EXIT,
+            (0, 0, 0xF, 0xE) => LOW,
+            (0, 0, 0xF, 0xF) => HIGH,
+            (0, _, _, _) => SYS(nnn),
+            (1, _, _, _) => JUMP(nnn),
+            (2, _, _, _) => CALL(nnn),
+            (3, _, _, _) => SKE(x, nn),
+            (4, _, _, _) => SKNE(x, nn),
+            (5, _, _, 0) => SKRE(x, y),
+            (5, _, _, 2) => SAVE(x, y),
+            (5, _, _, 3) => LOADR(x, y),
+            (6, _, _, _) => LOAD(x, nn),
+            (7, _, _, _) => ADD(x, nn),
+            (8, _, _, 0) => MOVE(x, y),
+            (8, _, _, 1) => OR(x, y),
+            (8, _, _, 2) => AND(x, y),
+            (8, _, _, 3) => XOR(x, y),
+            (8, _, _, 4) => ADDR(x, y),
+            (8, _, _, 5) => SUB(x, y),
+            (8, _, _, 6) => SHR(x, y),
+            (8, _, _, 7) => SUBN(x, y),
+            (8, _, _, 0xE) => SHL(x, y),
+            (9, _, _, 0) => SKRNE(x, y),
+            (0xA, _, _, _) => LOADI(nnn),
+            (0xB, _, _, _) => JUMPI(nnn),
+            (0xC, _, _, _) => RAND(x, nn),
+            (0xD, _, _, 0) => DRAWL(x, y),
+            (0xD, _, _, _) => DRAW(x, y, n),
+            (0xE, _, 9, 0xE) => SKPR(x),
+            (0xE, _, 0xA, 1) => SKUP(x),
+            (0xF, 0, 0, 0) => LOADIL,
+            (0xF, _, 0, 1) => PLANE(x),
+            (0xF, 0, 0, 2) => AUDIO,
+            (0xF, _, 0, 7) => MOVED(x),
+            (0xF, _, 0, 0xA) => KEYD(x),
+            (0xF, _, 1, 5) => LOADD(x),
+            (0xF, _, 1, 8) => LOADS(x),
+            (0xF, _, 1, 0xE) => ADDI(x),
+            (0xF, _, 2, 9) => LDSPR(x),
+            (0xF, _, 3, 0) => LDHSPR(x),
+            (0xF, _, 3, 3) => BCD(x),
+            (0xF, _, 3, 0xA) => PITCH(x),
+            (0xF, _, 5, 5) => STOR(x),
+            (0xF, _, 6, 5) => READ(x),
+            (0xF, _, 7, 5) => SRPL(x),
+            (0xF, _, 8, 5) => LRPL(x),
+            (_, _, _, _) => return Err(DecodeError { opcode }),
+        };
+
+        Ok(instruction)
+    }
+
+    /// Decode an opcode as the instruction it is on a platform.
+    pub fn decode_for(opcode: u16, platform: Platform) -> Result<Self, DecodeError> {
+        match Instruction::decode(opcode)? {
+            i if i.is_available_on(platform) => Ok(i),
+            Instruction::DRAWL(x, y) => Ok(Instruction::DRAW(x, y, 0)),
+            Instruction::SCD(_)
+            | Instruction::SCU(_)
+            | Instruction::SCR
+            | Instruction::SCL
+            | Instruction::EXIT
+            | Instruction::LOW
+            | Instruction::HIGH => Ok(Instruction::SYS(opcode as usize & 0x0FFF)),
+            _ => Err(DecodeError {
+                opcode: opcode as usize,
+            }),
+        }
+    }
+
+    /// Does the platform have this instruction?
+    pub fn is_available_on(self, platform: Platform) -> bool {
+        use Instruction::*;
+
+        match self {
+            SCD(_) | SCR | SCL | EXIT | LOW | HIGH | DRAWL(..) | LDHSPR(_) | SRPL(_) | LRPL(_) => {
+                platform == Platform::SuperChip || platform == Platform::XoChip
+            }
+            SCU(_) | SAVE(..) | LOADR(..) | LOADIL | PLANE(_) | AUDIO | PITCH(_) => {
+                platform == Platform::XoChip
+            }
+            _ => true,
+        }
+    }
+
+    /// The opcode for the instruction.
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+
+        // Opcode from its nibbles, or its first nibble and an 8-bit constant or 12-bit address.
+        let xyn = |a: usize, x: usize, y: usize, n: usize| {
+            a << 12 | (x & 0xF) << 8 | (y & 0xF) << 4 | n & 0xF
+        };
+        let xnn = |a: usize, x: usize, nn: usize| a << 12 | (x & 0xF) << 8 | nn & 0xFF;
+        let nnn = |a: usize, nnn: usize| a << 12 | nnn & 0xFFF;
+
+        let opcode = match *self {
+            SCD(n) => xyn(0, 0, 0xC, n),
+            SCU(n) => xyn(0, 0, 0xD, n),
+            CLR => 0x00E0,
+            RTS => 0x00EE,
+            SCR => 0x00FB,
+            SCL => 0x00FC,
+            EXIT => 0x00FD,
+            LOW => 0x00FE,
+            HIGH => 0x00FF,
+            SYS(a) => nnn(0, a),
+            JUMP(a) => nnn(1, a),
+            CALL(a) => nnn(2, a),
+            SKE(x, v) => xnn(3, x, v),
+            SKNE(x, v) => xnn(4, x, v),
+            SKRE(x, y) => xyn(5, x, y, 0),
+            SAVE(x, y) => xyn(5, x, y, 2),
+            LOADR(x, y) => xyn(5, x, y, 3),
+            LOAD(x, v) => xnn(6, x, v),
+            ADD(x, v) => xnn(7, x, v),
+            MOVE(x, y) => xyn(8, x, y, 0),
+            OR(x, y) => xyn(8, x, y, 1),
+            AND(x, y) => xyn(8, x, y, 2),
+            XOR(x, y) => xyn(8, x, y, 3),
+            ADDR(x, y) => xyn(8, x, y, 4),
+            SUB(x, y) => xyn(8, x, y, 5),
+            SHR(x, y) => xyn(8, x, y, 6),
+            SUBN(x, y) => xyn(8, x, y, 7),
+            SHL(x, y) => xyn(8, x, y, 0xE),
+            SKRNE(x, y) => xyn(9, x, y, 0),
+            LOADI(a) => nnn(0xA, a),
+            JUMPI(a) => nnn(0xB, a),
+            RAND(x, v) => xnn(0xC, x, v),
+            DRAWL(x, y) => xyn(0xD, x, y, 0),
+            DRAW(x, y, n) => xyn(0xD, x, y, n),
+            SKPR(x) => xnn(0xE, x, 0x9E),
+            SKUP(x) => xnn(0xE, x, 0xA1),
+            LOADIL => 0xF000,
+            PLANE(x) => xnn(0xF, x, 0x01),
+            AUDIO => 0xF002,
+            MOVED(x) => xnn(0xF, x, 0x07),
+            KEYD(x) => xnn(0xF, x, 0x0A),
+            LOADD(x) => xnn(0xF, x, 0x15),
+            LOADS(x) => xnn(0xF, x, 0x18),
+            ADDI(x) => xnn(0xF, x, 0x1E),
+            LDSPR(x) => xnn(0xF, x, 0x29),
+            LDHSPR(x) => xnn(0xF, x, 0x30),
+            BCD(x) => xnn(0xF, x, 0x33),
+            PITCH(x) => xnn(0xF, x, 0x3A),
+            STOR(x) => xnn(0xF, x, 0x55),
+            READ(x) => xnn(0xF, x, 0x65),
+            SRPL(x) => xnn(0xF, x, 0x75),
+            LRPL(x) => xnn(0xF, x, 0x85),
+        };
+
+        opcode as u16
+    }
+
+    /// Bytes the instruction takes in memory. 4 for `LOADIL` and its address, otherwise 2.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LOADIL => 4,
+            _ => 2,
+        }
+    }
+
+    /// The mnemonic alone, eg. `LOAD` for `LOAD V3, 0x2A`.
+    pub fn mnemonic(&self) -> &'static str {
+        use Instruction::*;
+
+        match self {
+            SCD(..) => "SCD",
+            SCU(..) => "SCU",
+            CLR => "CLR",
+            RTS => "RTS",
+            SCR => "SCR",
+            SCL => "SCL",
+            EXIT => "EXIT",
+            LOW => "LOW",
+            HIGH => "HIGH",
+            SYS(..) => "SYS",
+            JUMP(..) => "JUMP",
+            CALL(..) => "CALL",
+            SKE(..) => "SKE",
+            SKNE(..) => "SKNE",
+            SKRE(..) => "SKRE",
+            SAVE(..) => "SAVE",
+            LOADR(..) => "LOADR",
+            LOAD(..) => "LOAD",
+            ADD(..) => "ADD",
+            MOVE(..) => "MOVE",
+            OR(..) => "OR",
+            AND(..) => "AND",
+            XOR(..) => "XOR",
+            ADDR(..) => "ADDR",
+            SUB(..) => "SUB",
+            SHR(..) => "SHR",
+            SUBN(..) => "SUBN",
+            SHL(..) => "SHL",
+            SKRNE(..) => "SKRNE",
+            LOADI(..) => "LOADI",
+            JUMPI(..) => "JUMPI",
+            RAND(..) => "RAND",
+            DRAWL(..) => "DRAWL",
+            DRAW(..) => "DRAW",
+            SKPR(..) => "SKPR",
+            SKUP(..) => "SKUP",
+            LOADIL => "LOADIL",
+            PLANE(..) => "PLANE",
+            AUDIO => "AUDIO",
+            MOVED(..) => "MOVED",
+            KEYD(..) => "KEYD",
+            LOADD(..) => "LOADD",
+            LOADS(..) => "LOADS",
+            ADDI(..) => "ADDI",
+            LDSPR(..) => "LDSPR",
+            LDHSPR(..) => "LDHSPR",
+            BCD(..) => "BCD",
+            PITCH(..) => "PITCH",
+            STOR(..) => "STOR",
+            READ(..) => "READ",
+            SRPL(..) => "SRPL",
+            LRPL(..) => "LRPL",
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// The mnemonic and operands, eg. `LOAD V3, 0x2A` or `DRAW V0, V1, 5`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            SCD(n) => write!(f, "SCD {}", n),
+            SCU(n) => write!(f, "SCU {}", n),
+            CLR => write!(f, "CLR"),
+            RTS => write!(f, "RTS"),
+            SCR => write!(f, "SCR"),
+            SCL => write!(f, "SCL"),
+            EXIT => write!(f, "EXIT"),
+            LOW => write!(f, "LOW"),
+            HIGH => write!(f, "HIGH"),
+            SYS(a) => write!(f, "SYS {:#05X}", a),
+            JUMP(a) => write!(f, "JUMP {:#05X}", a),
+            CALL(a) => write!(f, "CALL {:#05X}", a),
+            SKE(x, v) => write!(f, "SKE V{:X}, {:#04X}", x, v),
+            SKNE(x, v) => write!(f, "SKNE V{:X}, {:#04X}", x, v),
+            SKRE(x, y) => write!(f, "SKRE V{:X}, V{:X}", x, y),
+            SAVE(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            LOADR(x, y) => write!(f, "LOADR V{:X}, V{:X}", x, y),
+            LOAD(x, v) => write!(f, "LOAD V{:X}, {:#04X}", x, v),
+            ADD(x, v) => write!(f, "ADD V{:X}, {:#04X}", x, v),
+            MOVE(x, y) => write!(f, "MOVE V{:X}, V{:X}", x, y),
+            OR(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            AND(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            XOR(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            ADDR(x, y) => write!(f, "ADDR V{:X}, V{:X}", x, y),
+            SUB(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            SHR(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SUBN(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            SHL(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SKRNE(x, y) => write!(f, "SKRNE V{:X}, V{:X}", x, y),
+            LOADI(a) => write!(f, "LOADI {:#05X}", a),
+            JUMPI(a) => write!(f, "JUMPI {:#05X}", a),
+            RAND(x, v) => write!(f, "RAND V{:X}, {:#04X}", x, v),
+            DRAWL(x, y) => write!(f, "DRAWL V{:X}, V{:X}", x, y),
+            DRAW(x, y, n) => write!(f, "DRAW V{:X}, V{:X}, {}", x, y, n),
+            SKPR(x) => write!(f, "SKPR V{:X}", x),
+            SKUP(x) => write!(f, "SKUP V{:X}", x),
+            LOADIL => write!(f, "LOADIL"),
+            PLANE(x) => write!(f, "PLANE {}", x),
+            AUDIO => write!(f, "AUDIO"),
+            MOVED(x) => write!(f, "MOVED V{:X}", x),
+            KEYD(x) => write!(f, "KEYD V{:X}", x),
+            LOADD(x) => write!(f, "LOADD V{:X}", x),
+            LOADS(x) => write!(f, "LOADS V{:X}", x),
+            ADDI(x) => write!(f, "ADDI V{:X}", x),
+            LDSPR(x) => write!(f, "LDSPR V{:X}", x),
+            LDHSPR(x) => write!(f, "LDHSPR V{:X}", x),
+            BCD(x) => write!(f, "BCD V{:X}", x),
+            PITCH(x) => write!(f, "PITCH V{:X}", x),
+            STOR(x) => write!(f, "STOR V{:X}", x),
+            READ(x) => write!(f, "READ V{:X}", x),
+            SRPL(x) => write!(f, "SRPL V{:X}", x),
+            LRPL(x) => write!(f, "LRPL V{:X}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every opcode that decodes encodes back to itself.
+    #[test]
+    fn test_decode_encode_round_trip() {
+        let mut decoded = 0;
+        for opcode in 0..=0xFFFF {
+            if let Ok(instruction) = Instruction::decode(opcode) {
+                assert_eq!(instruction.encode(), opcode, "{}", instruction);
+                assert_eq!(
+                    instruction.to_string().split(' ').next(),
+                    Some(instruction.mnemonic())
+                );
+                decoded += 1;
+            }
+        }
+        assert!(decoded > 0xB000);
+
+        assert_eq!(
+            Instruction::decode(0x8AB9),
+            Err(DecodeError { opcode: 0x8AB9 })
+        );
+    }
+
+    /// Instructions a platform doesn't have are decoded as its own, or rejected.
+    #[test]
+    fn test_decode_for_platform() {
+        assert_eq!(Instruction::decode(0xD120), Ok(Instruction::DRAWL(1, 2)));
+        assert_eq!(
+            Instruction::decode_for(0xD120, Platform::CosmacVip),
+            Ok(Instruction::DRAW(1, 2, 0))
+        );
+        assert_eq!(
+            Instruction::decode_for(0x00FF, Platform::Chip48),
+            Ok(Instruction::SYS(0x0FF))
+        );
+        assert_eq!(
+            Instruction::decode_for(0x00FF, Platform::SuperChip),
+            Ok(Instruction::HIGH)
+        );
+        assert!(Instruction::decode_for(0xF002, Platform::SuperChip).is_err());
+        assert_eq!(
+            Instruction::decode_for(0xF002, Platform::XoChip),
+            Ok(Instruction::AUDIO)
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Instruction::LOAD(3, 0x2A).to_string(), "LOAD V3, 0x2A");
+        assert_eq!(Instruction::DRAW(0, 0xA, 5).to_string(), "DRAW V0, VA, 5");
+        assert_eq!(Instruction::JUMP(0x208).to_string(), "JUMP 0x208");
+    }
+}
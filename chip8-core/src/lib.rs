@@ -0,0 +1,56 @@
+//! The emulated Chip8 machine, with no I/O of its own.
+//!
+//! A frontend loads a ROM, then 60 times a second sets the key state, runs a frame of
+//! instructions and draws the framebuffer when it changes:
+//!
+//! ```
+//! use chip8_core::{Chip8, Platform};
+//!
+//! let mut machine = Chip8::new(Platform::CosmacVip);
+//! machine.load_program(&[0x60, 0x05, 0x12, 0x02]).unwrap(); // V0 = 5, then loop forever.
+//!
+//! for _ in 0..10 {
+//!     machine.set_keys([false; 16]);
+//!     machine.run_frame(Platform::CosmacVip.instructions_per_frame()).unwrap();
+//!
+//!     if machine.has_graphics_update {
+//!         let (width, height) = machine.display_size();
+//!         assert_eq!(machine.graphics_buffer.len(), width * height);
+//!         machine.has_graphics_update = false;
+//!     }
+//! }
+//! ```
+//!
+//! `tick` runs a single instruction without touching the timers, for stepping through code.
+//! `RomOptions` and `FramePacer` are the command line and 60hz loop the frontends share.
+mod assembler;
+mod chip8;
+mod compare;
+mod coverage;
+mod debug;
+mod disassembler;
+mod error;
+mod frontend;
+mod instruction;
+mod png;
+mod profile;
+mod quirks;
+mod rewind;
+mod snapshot;
+mod trace;
+mod watch;
+
+pub use crate::assembler::{assemble, assemble_with_source_map, SourceMap};
+pub use crate::chip8::{Chip8, StepOutcome};
+pub use crate::compare::{compare_trace, TraceComparison, TraceState};
+pub use crate::coverage::{Coverage, Usage};
+pub use crate::debug::Register;
+pub use crate::disassembler::{disassemble, DisassemblyLine};
+pub use crate::error::{AssembleError, Chip8Error, DecodeError, SnapshotError};
+pub use crate::frontend::{parse_number, FramePacer, RomOptions};
+pub use crate::instruction::Instruction;
+pub use crate::profile::{AddressCount, Profile, SubroutineCount};
+pub use crate::quirks::{Platform, Quirks};
+pub use crate::rewind::Rewind;
+pub use crate::trace::{parse_trace_range, TraceEntry};
+pub use crate::watch::{Access, WatchHit, Watchpoint};
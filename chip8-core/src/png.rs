@@ -0,0 +1,97 @@
+//! Just enough PNG to write an RGB image, uncompressed. Coverage heatmaps are small, so this
+//! saves a dependency on a real encoder.
+
+const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+const MAX_STORED_BLOCK: usize = 0xFFFF; // Largest uncompressed deflate block, in bytes.
+
+/// Encode `pixels`, 3 bytes (red, green, blue) per pixel in rows from the top left, as a PNG.
+pub(crate) fn encode(width: usize, height: usize, pixels: &[u8]) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8 bits, RGB, deflate, no filter, no interlace.
+
+    // Each row starts with its filter type, 0 for none.
+    let mut rows = Vec::with_capacity((width * 3 + 1) * height);
+    for row in pixels.chunks(width * 3) {
+        rows.push(0);
+        rows.extend_from_slice(row);
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib_stored(&rows));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// A chunk: its length, type, data, then a CRC of the type and data.
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// A zlib stream holding `data` in uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01]; // Deflate with a 32k window, no preset dictionary.
+    let blocks: Vec<&[u8]> = data.chunks(MAX_STORED_BLOCK).collect();
+    for (n, block) in blocks.iter().enumerate() {
+        let last = n + 1 == blocks.len();
+        stream.push(last as u8); // Stored, and whether it's the final block.
+        let len = block.len() as u16;
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    if blocks.is_empty() {
+        stream.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checksums match known values, and a 1x1 image has the expected chunks.
+    #[test]
+    fn test_encode() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+
+        let png = encode(1, 1, &[255, 0, 0]);
+        assert_eq!(&png[..8], SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xAE\x42\x60\x82");
+    }
+}
@@ -0,0 +1,254 @@
+use crate::chip8::Chip8;
+use crate::instruction::Instruction;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Where a program spends its cycles. See `Chip8::start_profile`.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub cycles: u64,              // Ticks since profiling started, run or waiting.
+    pub instructions: u64,        // Instructions run.
+    pub key_wait_cycles: u64,     // Ticks spent waiting in KEYD for a key.
+    pub display_wait_cycles: u64, // Ticks spent waiting in DRAW for the display.
+    pub addresses: HashMap<usize, AddressCount>, // Keyed by address.
+    pub classes: HashMap<&'static str, u64>, // Instructions run, by mnemonic, eg. `DRAW`.
+    pub subroutines: HashMap<usize, SubroutineCount>, // Keyed by the address called.
+    calls: Vec<Call>,             // Subroutines being run, innermost last.
+}
+
+/// How often the instruction at an address ran.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AddressCount {
+    pub count: u64,
+    pub instruction: Instruction, // As of the first time it ran.
+}
+
+/// What a subroutine cost, paired up from its CALLs and RTSs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SubroutineCount {
+    pub calls: u64,
+    pub inclusive: u64, // Instructions run by it and the subroutines it called, up to its RTS.
+    pub exclusive: u64, // Instructions run by it alone.
+}
+
+/// A subroutine that has been called and not yet returned.
+#[derive(Clone, Debug)]
+struct Call {
+    address: usize,
+    started_at: u64, // `instructions` when it was called.
+    in_callees: u64, // Instructions run by the subroutines it called.
+}
+
+impl Profile {
+    fn instruction(&mut self, pc: usize, instruction: Instruction) {
+        self.instructions += 1;
+
+        self.addresses
+            .entry(pc)
+            .or_insert(AddressCount {
+                count: 0,
+                instruction,
+            })
+            .count += 1;
+
+        *self.classes.entry(instruction.mnemonic()).or_insert(0) += 1;
+
+        match instruction {
+            Instruction::CALL(address) => self.calls.push(Call {
+                address,
+                started_at: self.instructions,
+                in_callees: 0,
+            }),
+            // A return the profile didn't see the call for, eg. from before it started, is ignored.
+            Instruction::RTS => {
+                if let Some(call) = self.calls.pop() {
+                    let inclusive = self.instructions - call.started_at;
+                    let count = self.subroutines.entry(call.address).or_default();
+                    count.calls += 1;
+                    count.inclusive += inclusive;
+                    count.exclusive += inclusive - call.in_callees;
+                    if let Some(caller) = self.calls.last_mut() {
+                        caller.in_callees += inclusive;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// A report of the `top` busiest addresses, and every opcode class and subroutine.
+    pub fn report(&self, top: usize) -> String {
+        let percent = |n: u64| 100.0 * n as f64 / self.instructions.max(1) as f64;
+        let mut text = String::new();
+
+        writeln!(
+            text,
+            "{} instructions run in {} cycles.",
+            self.instructions, self.cycles
+        )
+        .unwrap();
+        writeln!(
+            text,
+            "Waiting for a key (KEYD): {} cycles ({:.1}%).",
+            self.key_wait_cycles,
+            100.0 * self.key_wait_cycles as f64 / self.cycles.max(1) as f64
+        )
+        .unwrap();
+        writeln!(
+            text,
+            "Waiting for the display: {} cycles ({:.1}%).",
+            self.display_wait_cycles,
+            100.0 * self.display_wait_cycles as f64 / self.cycles.max(1) as f64
+        )
+        .unwrap();
+
+        let mut addresses: Vec<(&usize, &AddressCount)> = self.addresses.iter().collect();
+        addresses.sort_by_key(|&(&address, count)| (std::cmp::Reverse(count.count), address));
+        writeln!(text, "\nTop addresses:").unwrap();
+        for (address, count) in addresses.iter().take(top) {
+            writeln!(
+                text,
+                "  {:#05X}  {:>10}  {:>5.1}%  {}",
+                address,
+                count.count,
+                percent(count.count),
+                count.instruction
+            )
+            .unwrap();
+        }
+
+        let mut classes: Vec<(&&str, &u64)> = self.classes.iter().collect();
+        classes.sort_by_key(|&(name, &count)| (std::cmp::Reverse(count), name));
+        writeln!(text, "\nInstructions:").unwrap();
+        for (name, &count) in classes {
+            writeln!(
+                text,
+                "  {:<8}  {:>10}  {:>5.1}%",
+                name,
+                count,
+                percent(count)
+            )
+            .unwrap();
+        }
+
+        let mut subroutines: Vec<(&usize, &SubroutineCount)> = self.subroutines.iter().collect();
+        subroutines.sort_by_key(|&(&address, count)| (std::cmp::Reverse(count.inclusive), address));
+        writeln!(
+            text,
+            "\nSubroutines:          calls   inclusive   exclusive"
+        )
+        .unwrap();
+        for (address, count) in subroutines {
+            writeln!(
+                text,
+                "  {:#05X}          {:>10}  {:>10}  {:>10}",
+                address, count.calls, count.inclusive, count.exclusive
+            )
+            .unwrap();
+        }
+
+        text
+    }
+}
+
+/// Profiling. Each tick is counted, by the instruction run or what it waited for.
+impl Chip8 {
+    /// Count where cycles go from now on, starting from nothing.
+    pub fn start_profile(&mut self) {
+        self.profile = Some(Profile::default());
+    }
+
+    /// Stop profiling, returning what was counted.
+    pub fn stop_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    /// What has been counted so far, if profiling.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Count an instruction just run at `pc`.
+    pub(crate) fn profile_instruction(&mut self, pc: usize) {
+        if let Some(profile) = self.profile.as_mut() {
+            profile.cycles += 1;
+            if let Ok(instruction) = Instruction::decode_for(self.last_opcode as u16, self.platform)
+            {
+                profile.instruction(pc, instruction);
+            }
+        }
+    }
+
+    /// Count a tick that didn't run an instruction.
+    pub(crate) fn profile_wait(&mut self) {
+        let (key, display) = (self.wait_for_input, self.wait_for_vblank);
+        if let Some(profile) = self.profile.as_mut() {
+            profile.cycles += 1;
+            if key {
+                profile.key_wait_cycles += 1;
+            } else if display {
+                profile.display_wait_cycles += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Platform;
+
+    /// Addresses, instructions and subroutines are counted, with nested calls paired up.
+    #[test]
+    fn test_profile() {
+        // 0x200 CALL 0x206, 0x202 KEYD V0, 0x204 JUMP 0x204,
+        // 0x206 ADD V1 1, 0x208 CALL 0x20C, 0x20A RTS, 0x20C ADD V1 1, 0x20E RTS.
+        let mut machine = Chip8::new(Platform::default());
+        machine
+            .load_program(&[
+                0x22, 0x06, 0xF0, 0x0A, 0x12, 0x04, 0x71, 0x01, 0x22, 0x0C, 0x00, 0xEE, 0x71, 0x01,
+                0x00, 0xEE,
+            ])
+            .unwrap();
+        machine.start_profile();
+        for _ in 0..10 {
+            machine.tick().unwrap();
+        }
+
+        let profile = machine.profile().unwrap();
+        assert_eq!(profile.cycles, 10);
+        assert_eq!(profile.instructions, 7);
+        assert_eq!(profile.key_wait_cycles, 3);
+        assert_eq!(profile.addresses[&0x200].count, 1);
+        assert_eq!(
+            profile.addresses[&0x206].instruction,
+            Instruction::ADD(1, 1)
+        );
+        assert_eq!(profile.classes["ADD"], 2);
+        assert_eq!(profile.classes["CALL"], 2);
+        assert_eq!(
+            profile.subroutines[&0x20C],
+            SubroutineCount {
+                calls: 1,
+                inclusive: 2,
+                exclusive: 2
+            }
+        );
+        assert_eq!(
+            profile.subroutines[&0x206],
+            SubroutineCount {
+                calls: 1,
+                inclusive: 5,
+                exclusive: 3
+            }
+        );
+
+        let report = profile.report(3);
+        assert!(report.starts_with("7 instructions run in 10 cycles.\n"));
+        assert!(report.contains("Waiting for a key (KEYD): 3 cycles (30.0%).\n"));
+        assert!(report.contains("\n  0x206                   1           5           3\n"));
+
+        assert!(machine.stop_profile().is_some());
+        assert!(machine.profile().is_none());
+    }
+}
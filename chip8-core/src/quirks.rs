@@ -0,0 +1,97 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Behaviours that differ between CHIP8 interpreters.
+/// Games were written against whatever interpreter their author had, so a ROM written for the
+/// COSMAC VIP can misbehave on a CHIP-48 interpreter and vice versa.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    pub shift_uses_vy: bool, // SHR/SHL shift VY into VX instead of VX in place.
+    pub load_store_increments_i: bool, // STOR/READ leave I past the last register used.
+    pub jump_with_vx: bool,  // JUMPI adds VX (X is the high nibble of NNN), not V0.
+    pub clip_sprites: bool,  // DRAW clips sprites at the screen edge, not wrap.
+    pub vf_reset: bool,      // OR/AND/XOR reset VF to 0.
+    pub display_wait: bool,  // DRAW waits for the next 60hz vertical blank.
+}
+
+/// Named interpreters that a ROM may have been written for.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Platform {
+    #[default]
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    /// The quirks preset matching how this platform's interpreter behaves.
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::CosmacVip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_with_vx: false,
+                clip_sprites: true,
+                vf_reset: true,
+                display_wait: true,
+            },
+            Platform::Chip48 | Platform::SuperChip => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_with_vx: true,
+                clip_sprites: true,
+                vf_reset: false,
+                display_wait: false,
+            },
+            Platform::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_with_vx: false,
+                clip_sprites: false,
+                vf_reset: false,
+                display_wait: false,
+            },
+        }
+    }
+}
+
+impl Platform {
+    /// How many instructions a frame runs by default, roughly matching the platform's speed.
+    pub fn instructions_per_frame(self) -> usize {
+        match self {
+            Platform::CosmacVip | Platform::Chip48 => 15,
+            Platform::SuperChip => 30,
+            Platform::XoChip => 1000,
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vip" | "cosmac" => Ok(Platform::CosmacVip),
+            "chip48" => Ok(Platform::Chip48),
+            "schip" | "superchip" => Ok(Platform::SuperChip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "Unknown platform '{}'. Expected one of: vip, chip48, schip, xochip",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Platform::CosmacVip => "COSMAC VIP",
+            Platform::Chip48 => "CHIP-48",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,200 @@
+use crate::chip8::Chip8;
+use crate::error::SnapshotError;
+use std::collections::VecDeque;
+
+/// A ring buffer of past machine states, for stepping backwards through a run.
+///
+/// Only the newest state is kept whole. Every older state is stored as the bytes that differ
+/// from the state after it, which is small since most of memory and the display rarely change
+/// between frames. Once full, the oldest state is dropped for each new one.
+pub struct Rewind {
+    newest: Option<Vec<u8>>, // The last snapshot pushed, or stepped back to.
+    deltas: VecDeque<Delta>, // Each undoes the change to the state after it. Oldest first.
+    capacity: usize,
+}
+
+/// How to get a snapshot back from the one after it.
+enum Delta {
+    Spans(Vec<(usize, Vec<u8>)>), // (offset, bytes) to overwrite, for snapshots of the same size.
+    Whole(Vec<u8>),               // The display size changed, so the whole snapshot.
+}
+
+impl Rewind {
+    // Differences closer than this are stored as one span, as a span's offset costs as much.
+    const SPAN_GAP: usize = 8;
+
+    /// Keep up to `capacity` states to step back to.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            newest: None,
+            deltas: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record the machine's current state, eg. at the end of a frame.
+    pub fn push(&mut self, machine: &Chip8) {
+        let snapshot = machine.snapshot();
+
+        if let Some(previous) = self.newest.take() {
+            if self.capacity == 0 {
+                return;
+            }
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(Delta::between(&snapshot, previous));
+        }
+
+        self.newest = Some(snapshot);
+    }
+
+    /// Restore the machine to the state before the last one recorded, dropping the last one.
+    /// Returns false, leaving the machine alone, if there is nothing further back. If the state
+    /// can't be restored, it's kept, so nothing is lost.
+    pub fn step_back(&mut self, machine: &mut Chip8) -> Result<bool, SnapshotError> {
+        let (newest, delta) = match (self.newest.as_ref(), self.deltas.back()) {
+            (Some(newest), Some(delta)) => (newest, delta),
+            _ => return Ok(false),
+        };
+
+        let mut older = newest.clone();
+        delta.apply(&mut older);
+        machine.restore_snapshot(&older)?;
+        self.deltas.pop_back();
+        self.newest = Some(older);
+        Ok(true)
+    }
+
+    /// How many states there are to step back to.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Forget all recorded states, eg. when a different ROM is loaded.
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+    }
+}
+
+impl Delta {
+    /// The delta that turns `newer` back into `older`.
+    fn between(newer: &[u8], older: Vec<u8>) -> Self {
+        if newer.len() != older.len() {
+            return Delta::Whole(older);
+        }
+
+        let mut spans: Vec<(usize, Vec<u8>)> = Vec::new();
+        for (offset, (&new, &old)) in newer.iter().zip(older.iter()).enumerate() {
+            if new == old {
+                continue;
+            }
+
+            match spans.last_mut() {
+                Some((start, bytes)) if offset - (*start + bytes.len()) < Rewind::SPAN_GAP => {
+                    let end = *start + bytes.len();
+                    bytes.extend_from_slice(&older[end..=offset]);
+                }
+                _ => spans.push((offset, vec![old])),
+            }
+        }
+
+        Delta::Spans(spans)
+    }
+
+    fn apply(&self, snapshot: &mut Vec<u8>) {
+        match self {
+            Delta::Spans(spans) => {
+                for (offset, bytes) in spans {
+                    snapshot[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+                }
+            }
+            Delta::Whole(older) => snapshot.clone_from(older),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Platform;
+
+    /// A program that counts up in V0 forever, switching to hires partway.
+    fn counting_machine() -> Chip8 {
+        let mut machine = Chip8::new(Platform::SuperChip);
+        machine
+            .load_program(&[0x70, 0x01, 0x30, 0x05, 0x12, 0x00, 0x00, 0xFF, 0x12, 0x00])
+            .unwrap();
+        machine
+    }
+
+    /// Stepping back restores each recorded state in turn, across a change of display size.
+    #[test]
+    fn test_rewind_steps_back() {
+        let mut machine = counting_machine();
+        let mut rewind = Rewind::new(100);
+        let mut snapshots = Vec::new();
+
+        for _ in 0..10 {
+            rewind.push(&machine);
+            snapshots.push(machine.snapshot());
+            machine.run_frame(2).unwrap();
+        }
+        rewind.push(&machine);
+        assert_eq!(rewind.len(), 10);
+        assert_eq!(machine.display_size(), (128, 64));
+
+        while let Some(expected) = snapshots.pop() {
+            assert!(rewind.step_back(&mut machine).unwrap());
+            assert_eq!(machine.snapshot(), expected);
+        }
+        assert_eq!(machine.display_size(), (64, 32));
+        assert!(!rewind.step_back(&mut machine).unwrap());
+        assert!(rewind.is_empty());
+    }
+
+    /// A state that can't be restored is kept, to step back to on the right machine.
+    #[test]
+    fn test_rewind_failed_step_back() {
+        let mut machine = counting_machine();
+        let mut rewind = Rewind::new(10);
+        let expected = machine.snapshot();
+        rewind.push(&machine);
+        machine.run_frame(2).unwrap();
+        rewind.push(&machine);
+
+        let mut other = Chip8::new(Platform::SuperChip);
+        other.load_program(&[0x12, 0x00]).unwrap();
+        assert_eq!(
+            rewind.step_back(&mut other),
+            Err(SnapshotError::RomMismatch)
+        );
+        assert_eq!(rewind.len(), 1);
+
+        assert!(rewind.step_back(&mut machine).unwrap());
+        assert_eq!(machine.snapshot(), expected);
+    }
+
+    /// Once full, the oldest states are dropped.
+    #[test]
+    fn test_rewind_capacity() {
+        let mut machine = counting_machine();
+        let mut rewind = Rewind::new(3);
+
+        for _ in 0..6 {
+            rewind.push(&machine);
+            machine.run_frame(1).unwrap();
+        }
+        assert_eq!(rewind.len(), 3);
+
+        while rewind.step_back(&mut machine).unwrap() {}
+        // Back to the state after the second instruction, SKE.
+        assert_eq!(machine.program_counter, 0x204);
+        assert_eq!(machine.registers[0], 1);
+    }
+}
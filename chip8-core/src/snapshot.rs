@@ -0,0 +1,303 @@
+use crate::chip8::Chip8;
+use crate::error::SnapshotError;
+use crate::quirks::{Platform, Quirks};
+use std::convert::TryInto;
+
+/// Save states.
+/// A snapshot is a versioned binary encoding of the machine, tagged with a hash of the ROM it was
+/// running so it can't be restored over a different one. All numbers are little-endian.
+impl Chip8 {
+    const SNAPSHOT_MAGIC: &'static [u8; 4] = b"C8SS";
+    const SNAPSHOT_VERSION: u16 = 1;
+
+    /// Encode the machine's state. Everything is saved except the random number generator.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+
+        // Header.
+        w.bytes(Chip8::SNAPSHOT_MAGIC);
+        w.u16(Chip8::SNAPSHOT_VERSION);
+        w.u64(self.rom_hash);
+
+        // Machine setup.
+        w.u8(platform_id(self.platform));
+        w.bool(self.quirks.shift_uses_vy);
+        w.bool(self.quirks.load_store_increments_i);
+        w.bool(self.quirks.jump_with_vx);
+        w.bool(self.quirks.clip_sprites);
+        w.bool(self.quirks.vf_reset);
+        w.bool(self.quirks.display_wait);
+        w.u32(self.rom_size);
+
+        // CPU.
+        w.u64(self.cycle as u64);
+        w.u32(self.program_counter);
+        w.u32(self.index_register);
+        w.u16(self.last_opcode as u16);
+        self.registers.iter().for_each(|&v| w.u8(v));
+        w.u8(self.stack_pointer);
+        self.stack.iter().for_each(|&a| w.u32(a));
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+
+        // Input.
+        self.keys.iter().for_each(|&k| w.bool(k));
+        w.bool(self.wait_for_input);
+        w.u8(self.keyd_register);
+
+        // Memory.
+        w.u32(self.memory.len());
+        self.memory.iter().for_each(|&b| w.u8(b));
+        self.rpl_flags.iter().for_each(|&f| w.u8(f));
+
+        // Display.
+        w.bool(self.hires);
+        w.u8(self.selected_planes as usize);
+        w.bool(self.wait_for_vblank);
+        w.bool(self.has_exited);
+        w.u32(self.graphics_buffer.len());
+        w.bytes(&self.graphics_buffer);
+
+        // Audio.
+        self.audio_pattern.iter().for_each(|&b| w.u8(b));
+        w.u8(self.pitch);
+
+        w.0
+    }
+
+    /// Restore a state encoded by `snapshot`. The same ROM must be loaded.
+    /// On error, the machine is left as it was.
+    pub fn restore_snapshot(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let mut r = Reader(bytes);
+
+        // Header.
+        if r.take(4).ok() != Some(&Chip8::SNAPSHOT_MAGIC[..]) {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = r.u16()? as u16;
+        if version != Chip8::SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        if r.u64()? != self.rom_hash {
+            return Err(SnapshotError::RomMismatch);
+        }
+
+        let mut state = self.clone();
+
+        // Machine setup.
+        state.platform = platform_from_id(r.u8()?)?;
+        state.quirks = Quirks {
+            shift_uses_vy: r.bool()?,
+            load_store_increments_i: r.bool()?,
+            jump_with_vx: r.bool()?,
+            clip_sprites: r.bool()?,
+            vf_reset: r.bool()?,
+            display_wait: r.bool()?,
+        };
+        state.rom_size = r.u32()?;
+
+        // CPU.
+        state.cycle = r.u64()? as usize;
+        state.program_counter = r.u32()?;
+        state.index_register = r.u32()?;
+        state.last_opcode = r.u16()?;
+        for v in state.registers.iter_mut() {
+            *v = r.u8()?;
+        }
+        state.stack_pointer = r.u8()?;
+        if state.stack_pointer > state.stack.len() {
+            return Err(SnapshotError::Invalid("stack pointer"));
+        }
+        for a in state.stack.iter_mut() {
+            *a = r.u32()?;
+        }
+        state.delay_timer = r.u8()?;
+        state.sound_timer = r.u8()?;
+
+        // Input.
+        for k in state.keys.iter_mut() {
+            *k = r.bool()?;
+        }
+        state.wait_for_input = r.bool()?;
+        state.keyd_register = r.u8()?;
+        if state.keyd_register >= state.registers.len() {
+            return Err(SnapshotError::Invalid("KEYD register"));
+        }
+
+        // Memory.
+        let memory_size = r.u32()?;
+        if memory_size != Chip8::memory_size(state.platform) {
+            return Err(SnapshotError::Invalid("memory size"));
+        }
+        state.memory = r.take(memory_size)?.iter().map(|&b| b as usize).collect();
+        for f in state.rpl_flags.iter_mut() {
+            *f = r.u8()?;
+        }
+
+        // Display.
+        state.hires = r.bool()?;
+        state.selected_planes = r.u8()? as u8;
+        state.wait_for_vblank = r.bool()?;
+        state.has_exited = r.bool()?;
+        let (width, height) = state.display_size();
+        if r.u32()? != width * height {
+            return Err(SnapshotError::Invalid("display size"));
+        }
+        state.graphics_buffer = r.take(width * height)?.to_vec();
+        state.has_graphics_update = true;
+
+        // Audio.
+        for b in state.audio_pattern.iter_mut() {
+            *b = r.u8()?;
+        }
+        state.pitch = r.u8()?;
+        state.has_audio_update = true;
+
+        *self = state;
+        Ok(())
+    }
+}
+
+fn platform_id(platform: Platform) -> usize {
+    match platform {
+        Platform::CosmacVip => 0,
+        Platform::Chip48 => 1,
+        Platform::SuperChip => 2,
+        Platform::XoChip => 3,
+    }
+}
+
+fn platform_from_id(id: usize) -> Result<Platform, SnapshotError> {
+    match id {
+        0 => Ok(Platform::CosmacVip),
+        1 => Ok(Platform::Chip48),
+        2 => Ok(Platform::SuperChip),
+        3 => Ok(Platform::XoChip),
+        _ => Err(SnapshotError::Invalid("platform")),
+    }
+}
+
+/// Appends little-endian fields to a buffer.
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.0.push(value as u8);
+    }
+
+    fn u8(&mut self, value: usize) {
+        self.0.push(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: usize) {
+        self.bytes(&(value as u32).to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+/// Reads little-endian fields from the front of a buffer.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.0.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn bool(&mut self) -> Result<bool, SnapshotError> {
+        Ok(self.take(1)?[0] != 0)
+    }
+
+    fn u8(&mut self) -> Result<usize, SnapshotError> {
+        Ok(self.take(1)?[0] as usize)
+    }
+
+    fn u16(&mut self) -> Result<usize, SnapshotError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()) as usize)
+    }
+
+    fn u32(&mut self) -> Result<usize, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine_with_rom(rom: &[u8]) -> Chip8 {
+        let mut machine = Chip8::new(Platform::SuperChip);
+        machine.load_program(rom).unwrap();
+        machine
+    }
+
+    /// A restored snapshot matches the machine it was taken from.
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut machine = machine_with_rom(&[0x00, 0xFF, 0x60, 0x2A, 0x22, 0x00]);
+        machine.run_frame(3).unwrap();
+        machine.keys[4] = true;
+        machine.delay_timer = 30;
+        let snapshot = machine.snapshot();
+
+        let mut restored = machine_with_rom(&[0x00, 0xFF, 0x60, 0x2A, 0x22, 0x00]);
+        restored.restore_snapshot(&snapshot).unwrap();
+
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.display_size(), (128, 64));
+        assert_eq!(restored.registers[0], 0x2A);
+        assert_eq!(restored.stack_pointer, 1);
+        assert_eq!(restored.program_counter, 0x200);
+        assert!(restored.keys[4]);
+    }
+
+    /// Snapshots are rejected for another ROM, another format version or if cut short.
+    #[test]
+    fn test_snapshot_rejected() {
+        let snapshot = machine_with_rom(&[0x12, 0x00]).snapshot();
+
+        let mut other = machine_with_rom(&[0x12, 0x02]);
+        assert_eq!(
+            other.restore_snapshot(&snapshot),
+            Err(SnapshotError::RomMismatch)
+        );
+
+        let mut machine = machine_with_rom(&[0x12, 0x00]);
+        assert_eq!(
+            machine.restore_snapshot(b"PNG"),
+            Err(SnapshotError::NotASnapshot)
+        );
+
+        let mut newer = snapshot.clone();
+        newer[4] = 99;
+        assert_eq!(
+            machine.restore_snapshot(&newer),
+            Err(SnapshotError::UnsupportedVersion(99))
+        );
+
+        assert_eq!(
+            machine.restore_snapshot(&snapshot[..100]),
+            Err(SnapshotError::Truncated)
+        );
+    }
+}
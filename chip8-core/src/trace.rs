@@ -0,0 +1,200 @@
+use crate::chip8::Chip8;
+use crate::debug::Register;
+use crate::instruction::Instruction;
+use std::fmt;
+use std::ops::Range;
+
+/// An instruction run while tracing. See `Chip8::start_trace`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub cycle: usize,
+    pub pc: usize, // Address of the instruction.
+    pub opcode: usize,
+    pub instruction: Instruction,
+    pub changes: Vec<(Register, usize)>, // Registers the instruction changed, with their new values.
+    pub index: usize,                    // I after the instruction.
+}
+
+impl TraceEntry {
+    /// A line naming the columns of `TraceEntry` lines, to start a log with.
+    pub const HEADER: &'static str =
+        "#  cycle  pc     op    instruction        I        changed registers";
+}
+
+impl fmt::Display for TraceEntry {
+    /// A line of a trace log, eg. `      12  0x204  7301  ADD V3, 0x01       I=0x300  V3=05`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>8}  {:#05X}  {:04X}  {:<18} I={:#05X}",
+            self.cycle,
+            self.pc,
+            self.opcode,
+            self.instruction.to_string(),
+            self.index
+        )?;
+        for (register, value) in &self.changes {
+            match register {
+                Register::V(_) => write!(f, "  {}={:02X}", register, value)?,
+                _ => write!(f, "  {}={:X}", register, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Instructions being traced, until the frontend takes them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Trace {
+    range: Range<usize>, // Addresses of the instructions to record.
+    entries: Vec<TraceEntry>,
+}
+
+/// The registers a trace reports changes to. I is reported after every instruction.
+const TRACED_REGISTERS: [Register; 19] = [
+    Register::V(0x0),
+    Register::V(0x1),
+    Register::V(0x2),
+    Register::V(0x3),
+    Register::V(0x4),
+    Register::V(0x5),
+    Register::V(0x6),
+    Register::V(0x7),
+    Register::V(0x8),
+    Register::V(0x9),
+    Register::V(0xA),
+    Register::V(0xB),
+    Register::V(0xC),
+    Register::V(0xD),
+    Register::V(0xE),
+    Register::V(0xF),
+    Register::Sp,
+    Register::DelayTimer,
+    Register::SoundTimer,
+];
+
+/// Tracing. Each opcode run with its address in the traced range is recorded, for `take_trace`.
+impl Chip8 {
+    /// Record the instructions run at addresses in `range`, from now on.
+    pub fn start_trace(&mut self, range: Range<usize>) {
+        self.trace = Some(Trace {
+            range,
+            entries: Vec::new(),
+        });
+    }
+
+    /// Stop recording. Instructions already recorded are dropped.
+    pub fn stop_trace(&mut self) {
+        self.trace = None;
+    }
+
+    /// The instructions recorded since the last call, oldest first.
+    pub fn take_trace(&mut self) -> Vec<TraceEntry> {
+        match self.trace.as_mut() {
+            Some(trace) => std::mem::take(&mut trace.entries),
+            None => Vec::new(),
+        }
+    }
+
+    /// The registers before running the opcode at the program counter, if it's to be traced.
+    pub(crate) fn trace_before(&self) -> Option<[usize; 19]> {
+        match &self.trace {
+            Some(trace) if trace.range.contains(&self.program_counter) => {
+                let mut registers = [0; 19];
+                for (value, &register) in registers.iter_mut().zip(TRACED_REGISTERS.iter()) {
+                    *value = self.register(register);
+                }
+                Some(registers)
+            }
+            _ => None,
+        }
+    }
+
+    /// Record the opcode just run at `pc`, comparing the registers with those before it.
+    pub(crate) fn trace_after(&mut self, pc: usize, before: &[usize; 19]) {
+        let changes = TRACED_REGISTERS
+            .iter()
+            .zip(before.iter())
+            .map(|(&register, &old)| (register, old, self.register(register)))
+            .filter(|&(_, old, new)| old != new)
+            .map(|(register, _, new)| (register, new))
+            .collect();
+        let entry = TraceEntry {
+            cycle: self.cycle,
+            pc,
+            opcode: self.last_opcode,
+            instruction: Instruction::decode_for(self.last_opcode as u16, self.platform)
+                .unwrap_or(Instruction::SYS(self.last_opcode & 0xFFF)),
+            changes,
+            index: self.index_register,
+        };
+        if let Some(trace) = self.trace.as_mut() {
+            trace.entries.push(entry);
+        }
+    }
+}
+
+/// An inclusive range of addresses to trace, as `--trace-range` takes it, eg. `0x200-0x2ff`.
+/// Numbers are decimal, or hex starting with `0x`.
+pub fn parse_trace_range(value: &str) -> Result<Range<usize>, String> {
+    let parse = |n: &str| match n.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => n.parse().ok(),
+    };
+    let error = || {
+        format!(
+            "Expected a range of addresses such as 0x200-0x2ff, not '{}'.",
+            value
+        )
+    };
+
+    let (start, end) = value.split_once('-').ok_or_else(error)?;
+    match (parse(start), parse(end)) {
+        (Some(start), Some(end)) if start <= end => {
+            Ok(start..end.checked_add(1).ok_or_else(error)?)
+        }
+        _ => Err(error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Platform;
+
+    /// Instructions in the range are recorded with the registers they changed.
+    #[test]
+    fn test_trace() {
+        // 0x200 LOAD V3 0x05, 0x202 LOADI 0x300, 0x204 ADD V3 1, 0x206 JUMP 0x206.
+        let mut machine = Chip8::new(Platform::default());
+        machine
+            .load_program(&[0x63, 0x05, 0xA3, 0x00, 0x73, 0x01, 0x12, 0x06])
+            .unwrap();
+        machine.start_trace(0x200..0x206);
+
+        for _ in 0..5 {
+            machine.tick().unwrap();
+        }
+        let trace = machine.take_trace();
+
+        assert_eq!(trace.len(), 3);
+        assert_eq!(trace[0].changes, vec![(Register::V(3), 5)]);
+        assert_eq!(trace[1].changes, vec![]);
+        assert_eq!(trace[1].index, 0x300);
+        assert_eq!(
+            trace[2].to_string(),
+            "       3  0x204  7301  ADD V3, 0x01       I=0x300  V3=06"
+        );
+        assert!(machine.take_trace().is_empty());
+    }
+
+    /// Ranges include their end, and take decimal or hex.
+    #[test]
+    fn test_parse_trace_range() {
+        assert_eq!(parse_trace_range("0x200-0x2ff"), Ok(0x200..0x300));
+        assert_eq!(parse_trace_range("512-513"), Ok(512..514));
+        assert!(parse_trace_range("0x300-0x200").is_err());
+        assert!(parse_trace_range("0x200").is_err());
+        assert!(parse_trace_range("0-0xffffffffffffffff").is_err());
+    }
+}
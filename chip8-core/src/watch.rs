@@ -0,0 +1,206 @@
+use crate::chip8::Chip8;
+use crate::debug::Register;
+use std::fmt;
+use std::ops::Range;
+
+/// Something for the Chip8 to watch opcodes touching. See `Chip8::add_watchpoint`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Watchpoint {
+    Read(Range<usize>),  // Memory addresses read as data by DRAW, READ and the like.
+    Write(Range<usize>), // Memory addresses written by STOR, BCD and the like.
+    Register(Register),  // A register changed by an opcode.
+}
+
+/// An opcode touching a watchpoint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchHit {
+    pub pc: usize, // Address of the opcode.
+    pub opcode: usize,
+    pub access: Access,
+}
+
+/// What an opcode did to a watchpoint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read(usize),  // The first watched address read.
+    Write(usize), // The first watched address written.
+    Register {
+        register: Register,
+        old: usize,
+        new: usize,
+    },
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watchpoint::Read(range) => write!(f, "read {:#05X}..{:#05X}", range.start, range.end),
+            Watchpoint::Write(range) => {
+                write!(f, "write {:#05X}..{:#05X}", range.start, range.end)
+            }
+            Watchpoint::Register(register) => write!(f, "change {}", register),
+        }
+    }
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.access {
+            Access::Read(address) => write!(f, "Read {:#05X}", address)?,
+            Access::Write(address) => write!(f, "Wrote {:#05X}", address)?,
+            Access::Register { register, old, new } => {
+                write!(f, "Changed {} from {:#X} to {:#X}", register, old, new)?
+            }
+        }
+        write!(f, " with {:#06X} at {:#05X}", self.opcode, self.pc)
+    }
+}
+
+/// Watchpoints. Each opcode run checks them, and the first one it touches is held for
+/// `take_watch_hit`, with `tick` returning `StepOutcome::Watchpoint`.
+impl Chip8 {
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Remove the watchpoint at an index of `watchpoints`.
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        if index < self.watchpoints.len() {
+            Some(self.watchpoints.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// The watchpoint touched by the last opcode run, if any.
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    /// Stop at a read watchpoint if an opcode read any of `len` bytes from `start`.
+    pub(crate) fn watch_read(&mut self, start: usize, len: usize) {
+        let watched = self.watchpoints.iter().find_map(|w| match w {
+            Watchpoint::Read(range) => first_in_range(range, start, len),
+            _ => None,
+        });
+        if let Some(address) = watched {
+            self.hit_watchpoint(self.program_counter, Access::Read(address));
+        }
+    }
+
+    /// Note an opcode writing `len` bytes of memory from `start`.
+    pub(crate) fn watch_write(&mut self, start: usize, len: usize) {
+        let watched = self.watchpoints.iter().find_map(|w| match w {
+            Watchpoint::Write(range) => first_in_range(range, start, len),
+            _ => None,
+        });
+        if let Some(address) = watched {
+            self.hit_watchpoint(self.program_counter, Access::Write(address));
+        }
+    }
+
+    /// The values of the watched registers, to compare after running an opcode.
+    pub(crate) fn watched_registers(&self) -> Vec<(Register, usize)> {
+        self.watchpoints
+            .iter()
+            .filter_map(|w| match w {
+                Watchpoint::Register(register) => Some((*register, self.register(*register))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Note any of the watched registers having changed from the values given, by the opcode that
+    /// was at `pc`.
+    pub(crate) fn watch_registers(&mut self, pc: usize, before: &[(Register, usize)]) {
+        let changed = before
+            .iter()
+            .find(|&&(register, old)| self.register(register) != old);
+        if let Some(&(register, old)) = changed {
+            let new = self.register(register);
+            self.hit_watchpoint(pc, Access::Register { register, old, new });
+        }
+    }
+
+    /// Keep the first watchpoint touched by the opcode at `pc`. Memory is touched while the opcode
+    /// runs, with it still at the program counter, but registers are only compared after it has
+    /// moved on.
+    fn hit_watchpoint(&mut self, pc: usize, access: Access) {
+        if self.watch_hit.is_none() {
+            self.watch_hit = Some(WatchHit {
+                pc,
+                opcode: self.last_opcode,
+                access,
+            });
+        }
+    }
+}
+
+/// The first address of `start..start + len` that is in `range`.
+fn first_in_range(range: &Range<usize>, start: usize, len: usize) -> Option<usize> {
+    let first = start.max(range.start);
+    if first < range.end && first < start + len {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::StepOutcome;
+    use crate::quirks::Platform;
+
+    /// Memory and register watchpoints report the opcode that touched them.
+    #[test]
+    fn test_watchpoints() {
+        // 0x200 LOAD V0 0x7B, 0x202 LOADI 0x300, 0x204 BCD V0, 0x206 READ V1, 0x208 ADD V3 1.
+        let mut machine = Chip8::new(Platform::default());
+        machine
+            .load_program(&[0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33, 0xF1, 0x65, 0x73, 0x01])
+            .unwrap();
+        machine.add_watchpoint(Watchpoint::Write(0x302..0x310));
+        machine.add_watchpoint(Watchpoint::Read(0x301..0x302));
+        machine.add_watchpoint(Watchpoint::Register(Register::V(3)));
+
+        assert_eq!(machine.tick(), Ok(StepOutcome::Executed));
+        assert_eq!(machine.tick(), Ok(StepOutcome::Executed));
+        assert_eq!(machine.take_watch_hit(), None);
+
+        assert_eq!(machine.tick(), Ok(StepOutcome::Watchpoint));
+        let hit = machine.take_watch_hit().unwrap();
+        assert_eq!(hit.pc, 0x204);
+        assert_eq!(hit.opcode, 0xF033);
+        assert_eq!(hit.access, Access::Write(0x302));
+        assert_eq!(hit.to_string(), "Wrote 0x302 with 0xF033 at 0x204");
+
+        assert_eq!(machine.tick(), Ok(StepOutcome::Watchpoint));
+        assert_eq!(
+            machine.take_watch_hit().unwrap().access,
+            Access::Read(0x301)
+        );
+
+        assert_eq!(machine.tick(), Ok(StepOutcome::Watchpoint));
+        let hit = machine.take_watch_hit().unwrap();
+        assert_eq!(hit.pc, 0x208);
+        assert_eq!(
+            hit.access,
+            Access::Register {
+                register: Register::V(3),
+                old: 0,
+                new: 1
+            }
+        );
+
+        assert_eq!(
+            machine.remove_watchpoint(0),
+            Some(Watchpoint::Write(0x302..0x310))
+        );
+        assert_eq!(machine.watchpoints().len(), 2);
+    }
+}
@@ -0,0 +1,265 @@
+mod script;
+use chip8_core::{parse_number, parse_trace_range, Chip8, RomOptions, StepOutcome, TraceEntry};
+use script::InputScript;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::process;
+
+/// Exit codes, so CI can tell a misbehaving ROM apart from a bad invocation.
+const EXIT_CHIP8_ERROR: i32 = 1;
+const EXIT_USAGE_ERROR: i32 = 2;
+
+const USAGE: &str = "USAGE: chip8-headless <rom-file> [options]
+
+Options:
+    --platform <vip|chip48|schip|xochip>  Interpreter to emulate (default vip).
+    --cycles <n>                          Run n cycles.
+    --frames <n>                          Run n 60hz frames (default 60).
+    --ipf <n>                             Instructions per frame (default depends on platform).
+    --input <file>                        Key presses to make, by frame.
+    --seed <n>                            Seed for RAND (default 0).
+    --screen <file>                       Write the final display. PBM if it ends in .pbm.
+    --state <file>                        Write the final register dump. Default is stdout.
+    --trace <file>                        Log every instruction run, with the registers it changed.
+    --trace-range <start>-<end>           Only log instructions at these addresses, eg. 0x200-0x2ff.
+    --profile <file>                      Write where the cycles went: the busiest addresses, each
+                                          instruction's count, and the cost of each subroutine.
+    --coverage <file>                     Write which bytes of the ROM were run as code, read as
+                                          data, or never reached. A PNG heatmap if it ends in
+                                          .png. Give it twice for both.";
+
+/// How long to run the ROM for.
+enum Length {
+    Cycles(usize),
+    Frames(usize),
+}
+
+struct Options {
+    rom: RomOptions,
+    length: Length,
+    input: InputScript,
+    seed: u64,
+    screen: Option<String>,
+    state: Option<String>,
+    trace: Option<String>,
+    trace_range: Range<usize>,
+    profile: Option<String>,
+    coverage: Vec<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let (rom, flags) = RomOptions::parse(args)?;
+        let mut options = Self {
+            rom,
+            length: Length::Frames(60),
+            input: InputScript::empty(),
+            seed: 0,
+            screen: None,
+            state: None,
+            trace: None,
+            trace_range: 0..0x10000,
+            profile: None,
+            coverage: Vec::new(),
+        };
+
+        for (flag, value) in &flags {
+            match flag.as_str() {
+                "--cycles" => options.length = Length::Cycles(parse_number(flag, value)?),
+                "--frames" => options.length = Length::Frames(parse_number(flag, value)?),
+                "--input" => {
+                    let text = fs::read_to_string(value).map_err(|e| e.to_string())?;
+                    options.input = InputScript::parse(&text)?;
+                }
+                "--seed" => options.seed = parse_number(flag, value)?,
+                "--screen" => options.screen = Some(value.clone()),
+                "--state" => options.state = Some(value.clone()),
+                "--trace" => options.trace = Some(value.clone()),
+                "--trace-range" => options.trace_range = parse_trace_range(value)?,
+                "--profile" => options.profile = Some(value.clone()),
+                "--coverage" => options.coverage.push(value.clone()),
+                _ => return Err(format!("Unknown option {}.", flag)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Write the instructions traced since the last call to the log.
+fn write_trace(machine: &mut Chip8, log: &mut impl Write) -> io::Result<()> {
+    for entry in machine.take_trace() {
+        writeln!(log, "{}", entry)?;
+    }
+    Ok(())
+}
+
+/// The display as text, a row per line. `.` is off, `#` is on. XO-CHIP's second plane is `+`,
+/// and both planes `%`.
+fn screen_as_text(machine: &Chip8) -> String {
+    let (width, _) = machine.display_size();
+    machine
+        .graphics_buffer
+        .chunks(width)
+        .map(|row| {
+            let mut line: String = row
+                .iter()
+                .map(|&p| ['.', '#', '+', '%'][p as usize])
+                .collect();
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+/// The display as a plain PBM image. Any plane being set is a black pixel.
+fn screen_as_pbm(machine: &Chip8) -> String {
+    let (width, height) = machine.display_size();
+    let rows: Vec<String> = machine
+        .graphics_buffer
+        .chunks(width)
+        .map(|row| {
+            row.iter()
+                .map(|&p| if p > 0 { "1" } else { "0" })
+                .collect::<Vec<&str>>()
+                .join(" ")
+        })
+        .collect();
+
+    format!("P1\n{} {}\n{}\n", width, height, rows.join("\n"))
+}
+
+/// Write the register dump to its file (or stdout), and the display, profile and coverage if
+/// files were given for them.
+fn write_outputs(options: &Options, machine: &Chip8) -> Result<(), String> {
+    match &options.state {
+        Some(path) => {
+            fs::write(path, machine.dump_state()).map_err(|e| format!("{}: {}", path, e))?
+        }
+        None => print!("{}", machine.dump_state()),
+    }
+
+    if let Some(path) = &options.screen {
+        let screen = if path.ends_with(".pbm") {
+            screen_as_pbm(machine)
+        } else {
+            screen_as_text(machine)
+        };
+        fs::write(path, screen).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    if let (Some(path), Some(profile)) = (&options.profile, machine.profile()) {
+        fs::write(path, profile.report(20)).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    if let Some(coverage) = machine.coverage() {
+        for path in &options.coverage {
+            let report = if path.ends_with(".png") {
+                coverage.heatmap()
+            } else {
+                coverage.report().into_bytes()
+            };
+            fs::write(path, report).map_err(|e| format!("{}: {}", path, e))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let options = match Options::parse(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(EXIT_USAGE_ERROR);
+        }
+    };
+
+    let mut machine = Chip8::new(options.rom.platform);
+    machine.seed_rng(options.seed);
+    if let Err(e) = machine.load_rom(&options.rom.path) {
+        eprintln!("Could not load {}: {}", options.rom.path, e);
+        process::exit(EXIT_USAGE_ERROR);
+    }
+
+    if options.profile.is_some() {
+        machine.start_profile();
+    }
+    if !options.coverage.is_empty() {
+        machine.start_coverage();
+    }
+
+    let mut trace = match &options.trace {
+        Some(path) => {
+            machine.start_trace(options.trace_range.clone());
+            let log = File::create(path).and_then(|f| {
+                let mut log = BufWriter::new(f);
+                writeln!(log, "{}", TraceEntry::HEADER)?;
+                Ok(log)
+            });
+            match log {
+                Ok(log) => Some(log),
+                Err(e) => {
+                    eprintln!("Could not write {}: {}", path, e);
+                    process::exit(EXIT_USAGE_ERROR);
+                }
+            }
+        }
+        None => None,
+    };
+
+    let instructions_per_frame = options.rom.instructions_per_frame.max(1);
+    let cycles = match options.length {
+        Length::Cycles(n) => n,
+        Length::Frames(n) => n * instructions_per_frame,
+    };
+
+    // Run a cycle at a time rather than a frame at a time, so --cycles can stop mid-frame.
+    let mut result = Ok(());
+    for cycle in 0..cycles {
+        if cycle % instructions_per_frame == 0 {
+            machine.set_keys(options.input.keys_at(cycle / instructions_per_frame));
+        }
+
+        match machine.tick() {
+            Ok(StepOutcome::Exited) => break,
+            Ok(_) => (),
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+
+        if (cycle + 1) % instructions_per_frame == 0 {
+            machine.end_frame();
+        }
+
+        // Write the trace as it goes, rather than holding all of a long run in memory.
+        if let Some(log) = trace.as_mut() {
+            if let Err(e) = write_trace(&mut machine, log) {
+                eprintln!("Could not write trace. {}", e);
+                process::exit(EXIT_USAGE_ERROR);
+            }
+        }
+    }
+    // Exiting doesn't flush buffered writes.
+    if let Some(Err(e)) = trace.as_mut().map(|log| log.flush()) {
+        eprintln!("Could not write trace. {}", e);
+        process::exit(EXIT_USAGE_ERROR);
+    }
+
+    let output = write_outputs(&options, &machine);
+    if let Err(e) = output {
+        eprintln!("Could not write output. {}", e);
+        process::exit(EXIT_USAGE_ERROR);
+    }
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(EXIT_CHIP8_ERROR);
+    }
+}
@@ -0,0 +1,98 @@
+/// Key states to hold from given frames onwards, read from a text file.
+///
+/// Each line is a frame number followed by the keys (hex, 0-F) held from that frame on. A line
+/// with no keys releases all keys. Blank lines and anything after a `#` are ignored:
+///
+/// ```text
+/// # Hold 5 at frame 60, then 4 and 6 together at frame 90, then let go at frame 120.
+/// 60 5
+/// 90 4 6
+/// 120
+/// ```
+pub struct InputScript {
+    events: Vec<(usize, [bool; 16])>, // (frame, keys), sorted by frame.
+}
+
+impl InputScript {
+    /// A script that never presses any keys.
+    pub fn empty() -> Self {
+        Self { events: Vec::new() }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let frame = words
+                .next()
+                .unwrap_or("")
+                .parse::<usize>()
+                .map_err(|_| format!("Line {}: expected a frame number.", line_number + 1))?;
+
+            let mut keys = [false; 16];
+            for word in words {
+                match usize::from_str_radix(word, 16) {
+                    Ok(key) if key < 16 => keys[key] = true,
+                    _ => {
+                        return Err(format!(
+                            "Line {}: '{}' is not a key from 0 to F.",
+                            line_number + 1,
+                            word
+                        ))
+                    }
+                }
+            }
+
+            events.push((frame, keys));
+        }
+
+        // A stable sort keeps the last of several lines for the same frame winning.
+        events.sort_by_key(|&(frame, _)| frame);
+        Ok(Self { events })
+    }
+
+    /// The keys held during a frame.
+    pub fn keys_at(&self, frame: usize) -> [bool; 16] {
+        self.events
+            .iter()
+            .take_while(|&&(f, _)| f <= frame)
+            .last()
+            .map_or([false; 16], |&(_, keys)| keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys are held from their frame until the next line changes them.
+    #[test]
+    fn test_keys_at() {
+        let script = InputScript::parse("# comment\n10 5\n\n20 4 f # both\n30\n").unwrap();
+
+        assert_eq!(script.keys_at(0), [false; 16]);
+        assert!(script.keys_at(10)[5]);
+        assert!(script.keys_at(19)[5]);
+        assert!(script.keys_at(25)[4] && script.keys_at(25)[0xF] && !script.keys_at(25)[5]);
+        assert_eq!(script.keys_at(30), [false; 16]);
+    }
+
+    /// Bad lines are reported with their line number.
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            InputScript::parse("10 5\nabc").err().unwrap(),
+            "Line 2: expected a frame number."
+        );
+        assert_eq!(
+            InputScript::parse("10 G").err().unwrap(),
+            "Line 1: 'G' is not a key from 0 to F."
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use console::{Key, Term};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// What a key typed in the terminal does.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Press(usize), // A Chip8 key, 0-F.
+    TogglePause,
+    Step, // Run one instruction while paused.
+    Quit,
+    None,
+}
+
+/// Keys read from the terminal. Terminals only report keys being typed, not held or let go, so a
+/// Chip8 key counts as held for a few frames after each press. Holding a key down keeps it held
+/// through the terminal's key repeat.
+pub struct Keyboard {
+    keys: Receiver<Key>,
+    held: [usize; 16], // Frames each Chip8 key is still held for.
+}
+
+impl Keyboard {
+    /// Frames a key stays held after being typed. Long enough to bridge the delay before key
+    /// repeat starts, about half a second on most systems.
+    const HOLD_FRAMES: usize = 30;
+
+    /// The keyboard's left four columns laid out as the Chip8 keypad, as the SDL frontend has it.
+    ///
+    /// ```text
+    /// 1 2 3 4      1 2 3 C
+    /// q w e r  ->  4 5 6 D
+    /// a s d f      7 8 9 E
+    /// z x c v      A 0 B F
+    /// ```
+    const KEY_BINDINGS: [char; 16] = [
+        'x', '1', '2', '3', 'q', 'w', 'e', 'a', 's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+    ];
+
+    /// Start reading keys from the terminal, on a thread so the emulator never waits for them.
+    /// The terminal is in raw mode while a key is being read, so reading stops at a key that quits,
+    /// rather than leave the terminal raw when the process exits.
+    pub fn new() -> Self {
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            let terminal = Term::stdout();
+            while let Ok(key) = terminal.read_key_raw() {
+                let is_quit = action(&key) == Action::Quit;
+                if sender.send(key).is_err() || is_quit {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            keys,
+            held: [0; 16],
+        }
+    }
+
+    /// The actions typed since the last call. Chip8 key presses are held from now on.
+    pub fn actions(&mut self) -> Vec<Action> {
+        let actions: Vec<Action> = self.keys.try_iter().map(|key| action(&key)).collect();
+        for action in &actions {
+            if let Action::Press(key) = action {
+                self.held[*key] = Keyboard::HOLD_FRAMES;
+            }
+        }
+        actions
+    }
+
+    /// The Chip8 keys held this frame. Counts down how long each is held for.
+    pub fn chip8_keys(&mut self) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for (key, frames) in keys.iter_mut().zip(self.held.iter_mut()) {
+            *key = *frames > 0;
+            *frames = frames.saturating_sub(1);
+        }
+        keys
+    }
+}
+
+/// What a key does. Letters work with caps lock on.
+pub fn action(key: &Key) -> Action {
+    match key {
+        Key::Escape | Key::CtrlC => Action::Quit,
+        Key::Char(' ') => Action::TogglePause,
+        Key::ArrowRight => Action::Step,
+        Key::Char(c) => {
+            let c = c.to_ascii_lowercase();
+            match Keyboard::KEY_BINDINGS.iter().position(|&b| b == c) {
+                Some(key) => Action::Press(key),
+                None => Action::None,
+            }
+        }
+        _ => Action::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys map onto the keypad's grid, and the controls match the SDL frontend's.
+    #[test]
+    fn test_action() {
+        assert_eq!(action(&Key::Char('1')), Action::Press(0x1));
+        assert_eq!(action(&Key::Char('4')), Action::Press(0xC));
+        assert_eq!(action(&Key::Char('x')), Action::Press(0x0));
+        assert_eq!(action(&Key::Char('V')), Action::Press(0xF));
+        assert_eq!(action(&Key::Char('p')), Action::None);
+        assert_eq!(action(&Key::Char(' ')), Action::TogglePause);
+        assert_eq!(action(&Key::Escape), Action::Quit);
+    }
+}
@@ -0,0 +1,103 @@
+mod keys;
+mod screen;
+use chip8_core::{Chip8, FramePacer, RomOptions, StepOutcome};
+use keys::{Action, Keyboard};
+use screen::Screen;
+use std::env;
+use std::process;
+
+const USAGE: &str = "USAGE: chip8-tui <rom-file> [options]
+
+Options:
+    --platform <vip|chip48|schip|xochip>  Interpreter to emulate (default vip).
+    --ipf <n>                             Instructions per frame (default depends on platform).
+
+Keys 1-4, Q-R, A-F and Z-V are the Chip8 keypad. Space pauses, Right Arrow steps while paused,
+and Esc quits.";
+
+/// The ROM and how to run it. The TUI has no options of its own.
+fn parse_options(args: &[String]) -> Result<RomOptions, String> {
+    match RomOptions::parse(args)? {
+        (options, others) if others.is_empty() => Ok(options),
+        (_, others) => Err(format!("Unknown option {}.", others[0].0)),
+    }
+}
+
+/// Run the ROM in the terminal until Esc is pressed. An execution error, or the ROM exiting, stops
+/// it there so the state can be looked over before quitting.
+fn run(options: &RomOptions) -> Result<(), String> {
+    let mut machine = Chip8::new(options.platform);
+    machine
+        .load_rom(&options.path)
+        .map_err(|e| format!("Could not load {}: {}", options.path, e))?;
+
+    let mut screen = Screen::new().map_err(|e| e.to_string())?;
+    let mut keyboard = Keyboard::new();
+    let mut is_paused = false;
+    let mut stopped = None; // Why the ROM can't carry on, if it can't.
+
+    let mut pacer = FramePacer::start();
+    loop {
+        for action in keyboard.actions() {
+            match action {
+                Action::Quit => return Ok(()),
+                Action::TogglePause if stopped.is_none() => is_paused = !is_paused,
+                Action::Step if is_paused && stopped.is_none() => {
+                    if let Err(e) = machine.tick() {
+                        stopped = Some(format!("Stopped. {}", e));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if !is_paused {
+            machine.set_keys(keyboard.chip8_keys());
+            let reason = match machine.run_frame(options.instructions_per_frame) {
+                Ok(StepOutcome::Exited) => Some("The ROM exited.".to_string()),
+                Ok(_) => None,
+                Err(e) => Some(format!("Stopped. {}", e)),
+            };
+            if reason.is_some() {
+                stopped = reason;
+                is_paused = true;
+            }
+        }
+
+        let status = match (&stopped, is_paused) {
+            (Some(reason), _) => format!("{} Esc to quit.", reason),
+            (None, true) => {
+                "Paused. Space to resume, Right Arrow to step, Esc to quit.".to_string()
+            }
+            (None, false) => "Space to pause, Esc to quit.".to_string(),
+        };
+        screen
+            .draw(
+                &machine.graphics_buffer,
+                machine.display_size(),
+                &machine.dump_state(),
+                &status,
+            )
+            .map_err(|e| e.to_string())?;
+        machine.has_graphics_update = false;
+
+        pacer.wait();
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let options = match parse_options(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(&options) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,130 @@
+use console::Term;
+use std::io;
+
+/// Draws the Chip8 display in the terminal, two pixel rows per line of text using half-block
+/// characters, with a panel of text beside it and a status line below. Only lines that changed
+/// since the last draw are written, to keep the output small over SSH.
+pub struct Screen {
+    terminal: Term,
+    drawn: Vec<String>, // The lines on the terminal, top first.
+}
+
+impl Screen {
+    const GAP: &'static str = "  "; // Between the display and the panel.
+
+    pub fn new() -> io::Result<Self> {
+        let terminal = Term::buffered_stdout();
+        terminal.hide_cursor()?;
+        terminal.clear_screen()?;
+        Ok(Self {
+            terminal,
+            drawn: Vec::new(),
+        })
+    }
+
+    /// Draw the display, with the panel's lines beside it and the status under it.
+    pub fn draw(
+        &mut self,
+        buffer: &[u8],
+        size: (usize, usize),
+        panel: &str,
+        status: &str,
+    ) -> io::Result<()> {
+        let mut lines = side_by_side(&display_lines(buffer, size), panel);
+        lines.push(status.to_string());
+
+        // The display's size changes with hires mode. Start over rather than leave a ghost of
+        // the bigger one.
+        if lines.len() != self.drawn.len() {
+            self.terminal.clear_screen()?;
+            self.drawn.clear();
+        }
+
+        for (row, line) in lines.iter().enumerate() {
+            if self.drawn.get(row) != Some(line) {
+                self.terminal.move_cursor_to(0, row)?;
+                self.terminal.clear_line()?;
+                self.terminal.write_str(line)?;
+            }
+        }
+        self.terminal.flush()?;
+        self.drawn = lines;
+        Ok(())
+    }
+}
+
+impl Drop for Screen {
+    /// Leave the terminal as it was found, below what was drawn.
+    fn drop(&mut self) {
+        let _ = self.terminal.move_cursor_to(0, self.drawn.len());
+        let _ = self.terminal.show_cursor();
+        let _ = self.terminal.flush();
+    }
+}
+
+/// The display as lines of half blocks: `▀` is the top pixel of the pair on, `▄` the bottom, `█`
+/// both. Any plane being set is a pixel on. An odd last row is paired with an off row.
+fn display_lines(buffer: &[u8], (width, height): (usize, usize)) -> Vec<String> {
+    let pixel = |x: usize, y: usize| y < height && buffer[y * width + x] > 0;
+    (0..height)
+        .step_by(2)
+        .map(|y| {
+            (0..width)
+                .map(|x| match (pixel(x, y), pixel(x, y + 1)) {
+                    (false, false) => ' ',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (true, true) => '█',
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The display framed in a box, with the panel's lines to its right.
+fn side_by_side(display: &[String], panel: &str) -> Vec<String> {
+    let width = display.first().map_or(0, |line| line.chars().count());
+    let border = "─".repeat(width);
+
+    let mut framed = vec![format!("┌{}┐", border)];
+    framed.extend(display.iter().map(|line| format!("│{}│", line)));
+    framed.push(format!("└{}┘", border));
+
+    let panel: Vec<&str> = panel.lines().collect();
+    let blank = " ".repeat(width + 2);
+    (0..framed.len().max(panel.len()))
+        .map(|row| {
+            let left = framed.get(row).map_or(blank.as_str(), |l| l.as_str());
+            match panel.get(row) {
+                Some(text) => format!("{}{}{}", left, Screen::GAP, text),
+                None => left.to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each line of text is two rows of pixels.
+    #[test]
+    fn test_display_lines() {
+        // 3x3: the top row all on, the middle row only its first pixel, the last only its last.
+        let buffer = [1, 1, 1, 1, 0, 0, 0, 0, 2];
+        assert_eq!(
+            display_lines(&buffer, (3, 3)),
+            vec!["█▀▀".to_string(), "  ▀".to_string()]
+        );
+    }
+
+    /// The display is framed, with the panel beside it, running on below it if it's longer.
+    #[test]
+    fn test_side_by_side() {
+        let lines = side_by_side(&["▀▄".to_string()], "PC: 0\nSP: 0\nI:  0\nVF: 1");
+        assert_eq!(
+            lines,
+            vec!["┌──┐  PC: 0", "│▀▄│  SP: 0", "└──┘  I:  0", "      VF: 1"]
+        );
+    }
+}
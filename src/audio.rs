@@ -1,8 +1,9 @@
-use rodio::{source::SineWave, Device, Sink};
+use rodio::{source::SineWave, Device, Sink, Source};
+use std::time::Duration;
 
 pub struct Audio {
     sink: Sink,
-    _device: Device, // Needs to be held but not used.
+    device: Device, // Needs to be held for the sink to play.
 }
 
 impl Audio {
@@ -15,9 +16,20 @@ impl Audio {
         sink.append(source);
         sink.pause(); // Start without playing.
 
-        Self {
-            sink: sink,
-            _device: device,
+        Self { sink, device }
+    }
+
+    /// Replace the sinewave tone with an XO-CHIP 128-bit pattern, looped at `rate` bits per
+    /// second. Whether the tone is playing or paused is kept.
+    pub fn set_pattern(&mut self, pattern: &[usize; 16], rate: f32) {
+        let was_paused = self.is_paused();
+
+        self.sink.stop();
+        self.sink = Sink::new(&self.device);
+        self.sink.append(PatternWave::new(pattern, rate));
+
+        if was_paused {
+            self.sink.pause();
         }
     }
 
@@ -36,3 +48,59 @@ impl Audio {
         self.sink.is_paused()
     }
 }
+
+/// An infinite source that loops a 128-bit pattern, each bit a high or low sample.
+struct PatternWave {
+    bits: [bool; 128],
+    rate: f32,
+    num_sample: usize,
+}
+
+impl PatternWave {
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn new(pattern: &[usize; 16], rate: f32) -> Self {
+        let mut bits = [false; 128];
+        for (i, bit) in bits.iter_mut().enumerate() {
+            *bit = pattern[i / 8] & (0x80 >> (i % 8)) > 0;
+        }
+
+        Self {
+            bits,
+            rate,
+            num_sample: 0,
+        }
+    }
+}
+
+impl Iterator for PatternWave {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.num_sample = self.num_sample.wrapping_add(1);
+
+        // Which bit of the pattern this sample falls on, at `rate` bits per second.
+        let position = self.num_sample as f32 * self.rate / Self::SAMPLE_RATE as f32;
+        let bit = position as usize % self.bits.len();
+
+        Some(if self.bits[bit] { 0.25 } else { -0.25 })
+    }
+}
+
+impl Source for PatternWave {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        Self::SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,346 @@
+use chip8_core::{Chip8, Register};
+use console::{Key, Term};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// The debugger's terminal UI. Panels show the code around the program counter, the registers,
+/// the stack, memory and the sprite at I, redrawn every frame as the Chip8 runs. Below them are
+/// the debugger's output and the command line.
+///
+/// PageUp and PageDown scroll the memory panel, which otherwise follows I, and Home puts it back.
+/// Up and Down scroll the output.
+pub struct Dashboard {
+    terminal: Term,
+    keys: Receiver<Key>,
+    input: String,                // The command being typed.
+    output: Vec<String>,          // Lines written by the debugger, oldest first.
+    output_scroll: usize,         // Lines scrolled back from the newest output.
+    memory_start: Option<usize>,  // First address in the memory panel, or None to follow I.
+    drawn: Vec<String>,           // The lines on the terminal, top first.
+    tty_settings: Option<String>, // The terminal's settings before keys were read raw.
+}
+
+impl Dashboard {
+    const PROMPT: &'static str = "(chip8) ";
+    const WIDTH: usize = 78; // Fits an 80 column terminal.
+    const CODE_LINES: usize = 12;
+    const MEMORY_ROWS: usize = 8; // Of 16 bytes each.
+    const SPRITE_BYTES: usize = 16; // Rows of the sprite previewed, the most DRAW reads.
+    const MIN_OUTPUT_LINES: usize = 6;
+    const OUTPUT_KEPT: usize = 1000; // Lines of output kept to scroll back through.
+
+    pub fn new() -> Self {
+        // Keys are read raw, and the thread reading them can't be stopped while it waits for one.
+        // Note the terminal's settings so they can be put back on exit.
+        let tty_settings = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            let terminal = Term::stdout();
+            while let Ok(key) = terminal.read_key_raw() {
+                if sender.send(key).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            terminal: Term::buffered_stdout(),
+            keys,
+            input: String::new(),
+            output: Vec::new(),
+            output_scroll: 0,
+            memory_start: None,
+            drawn: Vec::new(),
+            tty_settings,
+        }
+    }
+
+    /// Add to the output panel, scrolling it back to the newest line.
+    pub fn write(&mut self, text: &str) {
+        self.output.extend(text.lines().map(String::from));
+        let excess = self.output.len().saturating_sub(Dashboard::OUTPUT_KEPT);
+        self.output.drain(..excess);
+        self.output_scroll = 0;
+    }
+
+    /// Point the memory panel at an address, rather than following I.
+    pub fn show_memory(&mut self, address: usize) {
+        self.memory_start = Some(address & !0xF);
+    }
+
+    /// Handle the keys typed since the last call, returning a command if Enter was pressed. Keys
+    /// after the Enter wait for the next call.
+    pub fn next_command(&mut self, state: &Chip8) -> Option<String> {
+        let page = Dashboard::MEMORY_ROWS * 16;
+        let memory_start = self
+            .memory_start
+            .unwrap_or(state.register(Register::I) & !0xF);
+
+        while let Ok(key) = self.keys.try_recv() {
+            match key {
+                Key::Enter => {
+                    let line = std::mem::take(&mut self.input);
+                    self.write(&format!("{}{}", Dashboard::PROMPT, line));
+                    return Some(line);
+                }
+                Key::Char(c) if !c.is_control() => self.input.push(c),
+                Key::Backspace => {
+                    self.input.pop();
+                }
+                Key::CtrlC => self.input.clear(),
+                Key::PageUp => self.memory_start = Some(memory_start.saturating_sub(page)),
+                Key::PageDown => {
+                    let last = state.memory().len().saturating_sub(page);
+                    self.memory_start = Some((memory_start + page).min(last));
+                }
+                Key::Home => self.memory_start = None,
+                Key::ArrowUp => {
+                    self.output_scroll = (self.output_scroll + 1).min(self.output.len())
+                }
+                Key::ArrowDown => self.output_scroll = self.output_scroll.saturating_sub(1),
+                _ => (),
+            }
+        }
+        None
+    }
+
+    /// Redraw the panels from the machine's state. `code` is the listing around the program
+    /// counter. Only lines that changed are written.
+    pub fn draw(&mut self, state: &Chip8, code: &str) {
+        let lines = self.lines(state, code);
+
+        // Start over if the terminal was resized, rather than leave bits of the old layout.
+        let (rows, _) = self.terminal.size();
+        if lines.len() != self.drawn.len() {
+            let _ = self.terminal.clear_screen();
+            self.drawn.clear();
+        }
+
+        for (row, line) in lines.iter().enumerate().take(rows as usize) {
+            if self.drawn.get(row) != Some(line) {
+                let _ = self.terminal.move_cursor_to(0, row);
+                let _ = self.terminal.clear_line();
+                let _ = self.terminal.write_str(line);
+            }
+        }
+
+        // Leave the cursor after what's been typed.
+        let column = Dashboard::PROMPT.len() + self.input.chars().count();
+        let _ = self.terminal.move_cursor_to(column, lines.len() - 1);
+        let _ = self.terminal.flush();
+        self.drawn = lines;
+    }
+
+    /// Every line of the dashboard, top first, filling the terminal's height.
+    fn lines(&self, state: &Chip8, code: &str) -> Vec<String> {
+        let code: Vec<String> = code.lines().map(String::from).collect();
+        let mut lines = beside(&[
+            panel("Code", 40, Dashboard::CODE_LINES, &code),
+            panel(
+                "Registers",
+                16,
+                Dashboard::CODE_LINES,
+                &register_lines(state),
+            ),
+            panel("Stack", 16, Dashboard::CODE_LINES, &stack_lines(state)),
+        ]);
+
+        let i = state.register(Register::I);
+        let memory_start = self.memory_start.unwrap_or(i & !0xF);
+        let title = match self.memory_start {
+            Some(_) => "Memory",
+            None => "Memory at I",
+        };
+        let memory = memory_lines(state.memory(), memory_start, Dashboard::MEMORY_ROWS, i);
+        let sprite = state
+            .memory()
+            .get(i..)
+            .map(|bytes| sprite_lines(&bytes[..bytes.len().min(Dashboard::SPRITE_BYTES)]))
+            .unwrap_or_default();
+        lines.extend(beside(&[
+            panel(title, 56, Dashboard::MEMORY_ROWS, &memory),
+            panel("Sprite at I", 18, Dashboard::MEMORY_ROWS, &sprite),
+        ]));
+
+        // The output takes what's left of the terminal, above the command line.
+        let (rows, _) = self.terminal.size();
+        let height = (rows as usize)
+            .saturating_sub(lines.len() + 3)
+            .max(Dashboard::MIN_OUTPUT_LINES);
+        let end = self
+            .output
+            .len()
+            .saturating_sub(self.output_scroll)
+            .max(height.min(self.output.len()));
+        let shown = &self.output[end.saturating_sub(height)..end];
+        lines.extend(panel("Output", Dashboard::WIDTH - 2, height, shown));
+
+        lines.push(format!("{}{}", Dashboard::PROMPT, self.input));
+        lines
+    }
+}
+
+impl Drop for Dashboard {
+    /// Put the terminal's settings back, in case keys were being read raw, and leave the cursor
+    /// below the dashboard.
+    fn drop(&mut self) {
+        if let Some(settings) = &self.tty_settings {
+            let _ = Command::new("stty")
+                .arg(settings)
+                .stdin(Stdio::inherit())
+                .status();
+        }
+        let _ = self.terminal.write_line("");
+        let _ = self.terminal.flush();
+    }
+}
+
+/// A box of `width` by `height` characters inside its border, with a title on the top border.
+/// Lines that don't fit are cut off, and missing lines are blank.
+fn panel(title: &str, width: usize, height: usize, lines: &[String]) -> Vec<String> {
+    let title = format!(" {} ", title);
+    let mut boxed = vec![format!(
+        "┌{}{}┐",
+        title,
+        "─".repeat(width.saturating_sub(title.chars().count()))
+    )];
+    for row in 0..height {
+        let line: String = lines
+            .get(row)
+            .map_or("", |l| l)
+            .chars()
+            .take(width)
+            .collect();
+        let padding = width - line.chars().count();
+        boxed.push(format!("│{}{}│", line, " ".repeat(padding)));
+    }
+    boxed.push(format!("└{}┘", "─".repeat(width)));
+    boxed
+}
+
+/// Panels of the same height side by side.
+fn beside(panels: &[Vec<String>]) -> Vec<String> {
+    let height = panels.iter().map(Vec::len).max().unwrap_or(0);
+    (0..height)
+        .map(|row| {
+            panels
+                .iter()
+                .map(|p| p.get(row).map_or("", |l| l))
+                .collect()
+        })
+        .collect()
+}
+
+/// V0-VF in two columns, then I, the program counter, the stack pointer and the timers.
+fn register_lines(state: &Chip8) -> Vec<String> {
+    let v = |x| state.register(Register::V(x));
+    let mut lines: Vec<String> = (0..8)
+        .map(|x| format!("V{:X} {:02X}   V{:X} {:02X}", x, v(x), x + 8, v(x + 8)))
+        .collect();
+    lines.push(format!("I  {:#05X}", state.register(Register::I)));
+    lines.push(format!("PC {:#05X}", state.register(Register::Pc)));
+    lines.push(format!("SP {:X}", state.register(Register::Sp)));
+    lines.push(format!(
+        "DT {:02X}   ST {:02X}",
+        state.register(Register::DelayTimer),
+        state.register(Register::SoundTimer)
+    ));
+    lines
+}
+
+/// The return addresses on the stack, innermost first.
+fn stack_lines(state: &Chip8) -> Vec<String> {
+    let stack = state.stack();
+    if stack.is_empty() {
+        return vec!["(empty)".to_string()];
+    }
+    stack
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(depth, address)| format!("{:>2}  {:#05X}", depth, address))
+        .collect()
+}
+
+/// `rows` rows of 16 bytes from `start`, with the byte at I marked.
+fn memory_lines(memory: &[usize], start: usize, rows: usize, i: usize) -> Vec<String> {
+    (0..rows)
+        .map(|row| start + row * 16)
+        .take_while(|&address| address < memory.len())
+        .map(|address| {
+            let end = (address + 16).min(memory.len());
+            let bytes: String = (address..end)
+                .map(|a| {
+                    let marker = if a == i { '>' } else { ' ' };
+                    format!("{}{:02x}", marker, memory[a])
+                })
+                .collect();
+            format!("{:#05X}:{}", address, bytes)
+        })
+        .collect()
+}
+
+/// Sprite rows as pixels, two rows to a line with half blocks, and two characters to a pixel so
+/// it looks square.
+fn sprite_lines(bytes: &[usize]) -> Vec<String> {
+    bytes
+        .chunks(2)
+        .map(|rows| {
+            let top = rows[0];
+            let bottom = rows.get(1).copied().unwrap_or(0);
+            (0..8)
+                .rev()
+                .map(|bit| match ((top >> bit) & 1, (bottom >> bit) & 1) {
+                    (0, 0) => "  ",
+                    (1, 0) => "▀▀",
+                    (0, _) => "▄▄",
+                    _ => "██",
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Panels are boxed and titled, lines are cut to fit, and panels sit side by side.
+    #[test]
+    fn test_panels() {
+        let left = panel("A", 4, 2, &["1234567".to_string()]);
+        assert_eq!(left, vec!["┌ A ─┐", "│1234│", "│    │", "└────┘"]);
+
+        let right = panel("B", 2, 2, &[]);
+        assert_eq!(
+            beside(&[left, right]),
+            vec!["┌ A ─┐┌ B ┐", "│1234││  │", "│    ││  │", "└────┘└──┘"]
+        );
+    }
+
+    /// Memory rows mark the byte at I, and sprites are drawn two rows to a line.
+    #[test]
+    fn test_memory_and_sprite() {
+        let memory: Vec<usize> = (0..0x20).collect();
+        assert_eq!(
+            memory_lines(&memory, 0x10, 4, 0x11),
+            vec!["0x010: 10>11 12 13 14 15 16 17 18 19 1a 1b 1c 1d 1e 1f".to_string()]
+        );
+
+        assert_eq!(
+            sprite_lines(&[0b1100_0000, 0b1000_0001, 0b0000_0001]),
+            vec![
+                "██▀▀          ▄▄".to_string(),
+                "              ▀▀".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,678 @@
+use crate::dashboard::Dashboard;
+use chip8_core::{
+    disassemble, Chip8, DisassemblyLine, Instruction, Register, SourceMap, WatchHit, Watchpoint,
+};
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::path::Path;
+
+const HELP: &str = "Commands:
+    break <addr>            Stop before the instruction at addr is run. (b)
+    break <file:line>       Stop before the first instruction on a line of the source file.
+    delete <addr|file:line> Remove a breakpoint. (d)
+    breakpoints             List the breakpoints.
+    watch <addr> [len]      Stop when an opcode writes len bytes of memory, default 1.
+    watch <reg>             Stop when an opcode changes a register.
+    rwatch <addr> [len]     Stop when an opcode reads memory, eg. DRAW reading a sprite.
+    awatch <addr> [len]     Stop when an opcode reads or writes memory.
+    unwatch <n>             Remove the watchpoint numbered n.
+    watchpoints             List the watchpoints, numbered.
+    step [n]                Run n instructions, default 1. (s)
+    next                    Run one instruction, or a whole subroutine if it is a CALL. (n)
+    finish                  Run until the current subroutine returns. (f)
+    continue                Run until a breakpoint. (c)
+    pause                   Stop running. (p)
+    regs                    Show the registers, I, timers and stack. (r)
+    list [n]                Show n source lines or instructions around the program counter,
+                            default 10. (l)
+    set <reg> <value>       Change a register: v0-vf, i, pc, sp, dt or st.
+    mem <addr> [len]        Show len bytes of memory, default 16. (x)
+    write <addr> <byte>...  Change memory. (w)
+    profile [start|stop]    Show where the cycles have gone, start counting afresh, or stop.
+    help                    Show this. (h)
+Numbers are decimal, or hex starting with 0x.";
+
+/// A command typed at the debugger prompt.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Break(Location),
+    Delete(Location),
+    Breakpoints,
+    Watch(Vec<Watchpoint>),
+    Unwatch(usize),
+    Watchpoints,
+    Step(usize),
+    Next,
+    Finish,
+    Continue,
+    Pause,
+    Registers,
+    List(usize),
+    Set(Register, usize),
+    Memory(usize, usize),
+    Write(usize, Vec<u8>),
+    Profile,
+    ProfileStart,
+    ProfileStop,
+    Help,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((&name, args)) => (name, args),
+            None => return Err("No command given.".to_string()),
+        };
+
+        let command = match name {
+            "break" | "b" => Command::Break(location_arg(args, 0)?),
+            "delete" | "d" => Command::Delete(location_arg(args, 0)?),
+            "breakpoints" => Command::Breakpoints,
+            "watch" if args.first().is_some_and(|a| parse_number(a).is_err()) => {
+                let register = args[0].parse()?;
+                Command::Watch(vec![Watchpoint::Register(register)])
+            }
+            "watch" | "rwatch" | "awatch" => {
+                let start = number_arg(args, 0, "an address")?;
+                let len = match args.get(1) {
+                    Some(len) => parse_number(len)?,
+                    None => 1,
+                };
+                let range = memory_range(start, len)?;
+                Command::Watch(match name {
+                    "watch" => vec![Watchpoint::Write(range)],
+                    "rwatch" => vec![Watchpoint::Read(range)],
+                    _ => vec![Watchpoint::Read(range.clone()), Watchpoint::Write(range)],
+                })
+            }
+            "unwatch" => Command::Unwatch(number_arg(args, 0, "a watchpoint number")?),
+            "watchpoints" => Command::Watchpoints,
+            "step" | "s" if args.is_empty() => Command::Step(1),
+            "step" | "s" => Command::Step(number_arg(args, 0, "a count")?),
+            "next" | "n" => Command::Next,
+            "finish" | "f" => Command::Finish,
+            "continue" | "c" => Command::Continue,
+            "pause" | "p" => Command::Pause,
+            "regs" | "r" => Command::Registers,
+            "list" | "l" if args.is_empty() => Command::List(10),
+            "list" | "l" => Command::List(number_arg(args, 0, "a count")?),
+            "set" => {
+                let register: Register = args.first().ok_or("set needs a register.")?.parse()?;
+                let value = number_arg(args, 1, "a value")?;
+                if value > register.max_value() {
+                    return Err(format!(
+                        "{} holds at most {:#X}.",
+                        register,
+                        register.max_value()
+                    ));
+                }
+                Command::Set(register, value)
+            }
+            "mem" | "x" => {
+                let address = number_arg(args, 0, "an address")?;
+                let len = match args.len() {
+                    0 | 1 => 16,
+                    _ => number_arg(args, 1, "a length")?,
+                };
+                memory_range(address, len)?;
+                Command::Memory(address, len)
+            }
+            "write" | "w" => {
+                let bytes = (1..args.len().max(2))
+                    .map(|i| match number_arg(args, i, "bytes")? {
+                        b if b <= 0xFF => Ok(b as u8),
+                        b => Err(format!("{:#X} is not a byte.", b)),
+                    })
+                    .collect::<Result<Vec<u8>, String>>()?;
+                Command::Write(number_arg(args, 0, "an address")?, bytes)
+            }
+            "profile" => match args.first() {
+                None => Command::Profile,
+                Some(&"start") => Command::ProfileStart,
+                Some(&"stop") => Command::ProfileStop,
+                Some(arg) => return Err(format!("Expected start or stop, not '{}'.", arg)),
+            },
+            "help" | "h" | "?" => Command::Help,
+            _ => return Err(format!("Unknown command '{}'. Try help.", name)),
+        };
+
+        Ok(command)
+    }
+}
+
+/// A number, decimal or hex starting with 0x.
+fn parse_number(word: &str) -> Result<usize, String> {
+    let result = match word.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => word.parse(),
+    };
+    result.map_err(|_| format!("'{}' is not a number.", word))
+}
+
+/// The number at `args[index]`. `what` describes it if it's missing.
+fn number_arg(args: &[&str], index: usize, what: &str) -> Result<usize, String> {
+    parse_number(args.get(index).ok_or(format!("Expected {}.", what))?)
+}
+
+/// `len` bytes from `start`, checking the end is still an address.
+fn memory_range(start: usize, len: usize) -> Result<Range<usize>, String> {
+    let end = start.checked_add(len).ok_or(format!(
+        "{:#X} bytes at {:#X}: range overflows memory.",
+        len, start
+    ))?;
+    Ok(start..end)
+}
+
+/// The address or `file:line` at `args[index]`.
+fn location_arg(args: &[&str], index: usize) -> Result<Location, String> {
+    let word = args.get(index).ok_or("Expected an address or file:line.")?;
+    match word.rsplit_once(':') {
+        Some((file, line)) => Ok(Location::Line(file.to_string(), parse_number(line)?)),
+        None => parse_number(word).map(Location::Address),
+    }
+}
+
+/// Where a breakpoint goes.
+#[derive(Debug, PartialEq)]
+pub enum Location {
+    Address(usize),
+    Line(String, usize), // A file and line of the source being run.
+}
+
+/// The source file a program was assembled from, for showing and breaking on its lines.
+pub struct SourceFile {
+    path: String,
+    lines: Vec<String>,
+    map: SourceMap,
+}
+
+impl SourceFile {
+    pub fn new(path: &str, text: &str, map: SourceMap) -> Self {
+        Self {
+            path: path.to_string(),
+            lines: text.lines().map(String::from).collect(),
+            map,
+        }
+    }
+
+    /// The address of the first instruction on or after a line. `file` is the path, or the end of
+    /// it such as the file name.
+    fn address(&self, file: &str, line: usize) -> Result<usize, String> {
+        if !Path::new(&self.path).ends_with(file) {
+            return Err(format!("No source file '{}'. Running {}.", file, self.path));
+        }
+        self.map
+            .address(line)
+            .ok_or(format!("No code on or after {}:{}.", file, line))
+    }
+
+    /// `file:line`, and the source on that line.
+    fn describe(&self, line: usize) -> String {
+        let text = self.lines.get(line - 1).map_or("", |l| l.trim());
+        format!("{}:{}  {}", self.path, line, text)
+    }
+
+    /// Source lines around `line`, numbered, with it marked `=>`.
+    fn listing(&self, line: usize, before: usize, after: usize) -> String {
+        let first = line.saturating_sub(before).max(1);
+        let last = (line + after).min(self.lines.len() + 1);
+        (first..last)
+            .map(|n| {
+                let marker = if n == line { "=>" } else { "  " };
+                format!("{} {:>4}  {}", marker, n, self.lines[n - 1])
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// Where a run started by the debugger stops, besides at breakpoints.
+enum RunUntil {
+    Steps(usize),                    // After this many more instructions.
+    Return { pc: usize, sp: usize }, // Back after a CALL, at the same stack depth.
+    StackBelow(usize),               // Returned from the subroutine at this stack depth.
+}
+
+/// What the emulator should do after a debugger command.
+pub enum Control {
+    Pause,
+    Resume,
+    Continue, // Carry on as before.
+}
+
+/// A command prompt on the terminal for inspecting and controlling the Chip8, under a dashboard
+/// of its state. Keys are read on another thread, so the emulator keeps running (and its window
+/// responding) while waiting for them. See `HELP` for the commands.
+pub struct Debugger {
+    dashboard: Dashboard,
+    breakpoints: BTreeSet<usize>,
+    run_until: Option<RunUntil>,
+    source: Option<SourceFile>, // When running a source file rather than a ROM.
+    disassembly: Option<Disassembly>, // The last one listed, reused while it's current.
+}
+
+impl Debugger {
+    const CODE_BEFORE: usize = 4; // Instructions or source lines the dashboard shows before the PC.
+    const CODE_AFTER: usize = 8;
+    const PROFILE_TOP: usize = 20; // Busiest addresses a profile report shows.
+
+    pub fn new() -> Self {
+        Self {
+            dashboard: Dashboard::new(),
+            breakpoints: BTreeSet::new(),
+            run_until: None,
+            source: None,
+            disassembly: None,
+        }
+    }
+
+    /// Show the lines of the source file the program was assembled from, and allow breakpoints on them.
+    pub fn set_source(&mut self, source: SourceFile) {
+        self.source = Some(source);
+    }
+
+    fn resolve(&self, location: Location) -> Result<usize, String> {
+        match (location, &self.source) {
+            (Location::Address(address), _) => Ok(address),
+            (Location::Line(file, line), Some(source)) => source.address(&file, line),
+            (Location::Line(file, _), None) => {
+                Err(format!("No source file '{}'. Running a ROM.", file))
+            }
+        }
+    }
+
+    /// The address, with the source line it's on if there is one.
+    fn describe(&self, address: usize) -> String {
+        let source = self
+            .source
+            .as_ref()
+            .and_then(|s| s.map.line(address).map(|line| s.describe(line)));
+        match source {
+            Some(line) => format!("{:#05X} {}", address, line),
+            None => format!("{:#05X}", address),
+        }
+    }
+
+    /// Show in the dashboard's output.
+    pub fn write(&mut self, string: String) {
+        self.dashboard.write(&string);
+    }
+
+    /// Redraw the dashboard from the machine's state.
+    pub fn draw(&mut self, state: &Chip8) {
+        let code = self.code_listing(state, Debugger::CODE_BEFORE, Debugger::CODE_AFTER);
+        self.dashboard.draw(state, &code);
+    }
+
+    /// The next line typed at the prompt, if there is one waiting.
+    pub fn next_command(&mut self, state: &Chip8) -> Option<String> {
+        self.dashboard.next_command(state)
+    }
+
+    /// Run a line typed at the prompt.
+    pub fn run_command(&mut self, line: &str, state: &mut Chip8) -> Control {
+        if line.trim().is_empty() {
+            return Control::Continue;
+        }
+
+        match Command::parse(line) {
+            Ok(command) => self.execute(command, state),
+            Err(e) => {
+                self.write(e);
+                Control::Continue
+            }
+        }
+    }
+
+    /// Source lines around the program counter if it's in the source file, or the disassembly.
+    fn code_listing(&mut self, state: &Chip8, before: usize, after: usize) -> String {
+        let pc = state.register(Register::Pc);
+        let source = self
+            .source
+            .as_ref()
+            .and_then(|s| s.map.line(pc).map(|line| s.listing(line, before, after)));
+        source.unwrap_or_else(|| self.listing(state, before, after))
+    }
+
+    /// The disassembly of memory around the program counter, with the next instruction marked
+    /// `=>`. Memory is only disassembled again once the last disassembly is out of date.
+    fn listing(&mut self, state: &Chip8, before: usize, after: usize) -> String {
+        let disassembly = match self.disassembly.take() {
+            Some(disassembly) if disassembly.is_current(state) => disassembly,
+            _ => Disassembly::new(state),
+        };
+        let listing = disassembly.listing(state, before, after);
+        self.disassembly = Some(disassembly);
+        listing
+    }
+
+    fn execute(&mut self, command: Command, state: &mut Chip8) -> Control {
+        let pc = state.register(Register::Pc);
+        let sp = state.register(Register::Sp);
+
+        match command {
+            Command::Break(location) => match self.resolve(location) {
+                Ok(address) => {
+                    self.breakpoints.insert(address);
+                    self.write(format!("Breakpoint at {}", self.describe(address)));
+                }
+                Err(e) => self.write(e),
+            },
+            Command::Delete(location) => match self.resolve(location) {
+                Ok(address) if !self.breakpoints.remove(&address) => {
+                    self.write(format!("No breakpoint at {:#05X}.", address))
+                }
+                Ok(_) => (),
+                Err(e) => self.write(e),
+            },
+            Command::Breakpoints => {
+                let described: Vec<String> =
+                    self.breakpoints.iter().map(|&a| self.describe(a)).collect();
+                for line in described {
+                    self.write(line);
+                }
+            }
+            Command::Watch(watchpoints) => {
+                for watchpoint in watchpoints {
+                    state.add_watchpoint(watchpoint);
+                }
+            }
+            Command::Unwatch(n) => {
+                if state.remove_watchpoint(n).is_none() {
+                    self.write(format!("No watchpoint {}.", n));
+                }
+            }
+            Command::Watchpoints => {
+                for (n, watchpoint) in state.watchpoints().iter().enumerate() {
+                    self.write(format!("{}: {}", n, watchpoint));
+                }
+            }
+            Command::Step(0) => (),
+            Command::Step(n) => return self.resume(Some(RunUntil::Steps(n))),
+            Command::Next => match state.next_opcode().map(|op| Instruction::decode(op as u16)) {
+                Some(Ok(Instruction::CALL(_))) => {
+                    return self.resume(Some(RunUntil::Return { pc: pc + 2, sp }))
+                }
+                _ => return self.resume(Some(RunUntil::Steps(1))),
+            },
+            Command::Finish if sp == 0 => self.write("Not in a subroutine.".to_string()),
+            Command::Finish => return self.resume(Some(RunUntil::StackBelow(sp))),
+            Command::Continue => return self.resume(None),
+            Command::Pause => return Control::Pause,
+            Command::Registers => self.write(state.dump_state()),
+            Command::List(n) => {
+                let listing = self.code_listing(state, n / 3, n - n / 3);
+                self.write(listing);
+            }
+            Command::Set(register, value) => state.set_register(register, value),
+            Command::Memory(address, len) => match state.memory().get(address..address + len) {
+                Some(bytes) => {
+                    self.write(hex_dump(address, bytes));
+                    self.dashboard.show_memory(address);
+                }
+                None => self.write(format!(
+                    "{:#X} bytes at {:#05X} is out of bounds.",
+                    len, address
+                )),
+            },
+            Command::Write(address, bytes) => {
+                if let Err(e) = state.write_memory(address, &bytes) {
+                    self.write(e.to_string());
+                }
+            }
+            Command::Profile => match state.profile() {
+                Some(profile) => self.write(profile.report(Debugger::PROFILE_TOP)),
+                None => self.write("Not profiling. Try profile start.".to_string()),
+            },
+            Command::ProfileStart => {
+                state.start_profile();
+                self.write("Profiling.".to_string());
+            }
+            Command::ProfileStop => match state.stop_profile() {
+                Some(profile) => self.write(profile.report(Debugger::PROFILE_TOP)),
+                None => self.write("Not profiling.".to_string()),
+            },
+            Command::Help => self.write(HELP.to_string()),
+        }
+
+        Control::Continue
+    }
+
+    fn resume(&mut self, run_until: Option<RunUntil>) -> Control {
+        self.run_until = run_until;
+        Control::Resume
+    }
+
+    /// Check, after an instruction is run, whether the current run should stop at the next one.
+    /// If so the reason and state are shown.
+    pub fn should_stop(&mut self, state: &Chip8) -> bool {
+        let pc = state.register(Register::Pc);
+        let sp = state.register(Register::Sp);
+
+        let is_done = match self.run_until.as_mut() {
+            Some(RunUntil::Steps(n)) => {
+                *n -= 1;
+                *n == 0
+            }
+            Some(RunUntil::Return { pc: at, sp: depth }) => pc == *at && sp == *depth,
+            Some(RunUntil::StackBelow(depth)) => sp < *depth,
+            None => false,
+        };
+
+        if is_done {
+            self.stop(state, String::new());
+        } else if self.breakpoints.contains(&pc) {
+            self.stop(state, format!("Breakpoint at {:#05X}.\n", pc));
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Stop the current run because an opcode touched a watchpoint.
+    pub fn stop_at_watchpoint(&mut self, hit: WatchHit, state: &Chip8) {
+        self.stop(state, format!("Watchpoint. {}.\n", hit));
+    }
+
+    /// Show why the run stopped, and where: the source line if there is one, and the instruction.
+    /// The dashboard shows the rest of the state.
+    fn stop(&mut self, state: &Chip8, reason: String) {
+        self.run_until = None;
+        let pc = state.register(Register::Pc);
+        let source = self
+            .source
+            .as_ref()
+            .and_then(|s| s.map.line(pc).map(|line| s.describe(line) + "\n"));
+        let listing = self.listing(state, 0, 1);
+        self.write(format!(
+            "{}{}{}",
+            reason,
+            source.unwrap_or_default(),
+            listing
+        ));
+    }
+}
+
+/// All of memory disassembled, with code traced from the start of the ROM, the program counter
+/// and the return addresses on the stack. Tracing is too slow to redo every frame, so it's kept
+/// until a write lands in the code it found or the program counter isn't at an instruction.
+/// Writes to data don't change what's code, so data bytes are just read again when listed.
+struct Disassembly {
+    memory_len: usize, // Of the memory disassembled, which changes with the platform.
+    lines: Vec<DisassemblyLine>,
+}
+
+impl Disassembly {
+    fn new(state: &Chip8) -> Self {
+        let memory: Vec<u8> = state.memory().iter().map(|&b| b as u8).collect();
+        let mut entries = vec![0x200, state.register(Register::Pc)];
+        entries.extend(state.stack().iter().map(|&a| a + 2));
+
+        let lines = disassemble(&memory, 0, &entries, state.platform());
+        Self {
+            memory_len: memory.len(),
+            lines,
+        }
+    }
+
+    /// Whether the instructions are unchanged in memory and the program counter is at one.
+    fn is_current(&self, state: &Chip8) -> bool {
+        let pc = state.register(Register::Pc);
+        let memory = state.memory();
+        memory.len() == self.memory_len
+            && self
+                .lines
+                .binary_search_by_key(&pc, |l| l.address)
+                .is_ok_and(|n| self.lines[n].is_code)
+            && self.lines.iter().filter(|l| l.is_code).all(|l| {
+                l.bytes
+                    .iter()
+                    .zip(&memory[l.address..])
+                    .all(|(&a, &b)| a as usize == b)
+            })
+    }
+
+    fn listing(&self, state: &Chip8, before: usize, after: usize) -> String {
+        let (pc, memory) = (state.register(Register::Pc), state.memory());
+        let lines = &self.lines;
+        let current = lines.partition_point(|l| l.address < pc);
+
+        lines[current.saturating_sub(before)..(current + after).min(lines.len())]
+            .iter()
+            .map(|l| {
+                let marker = if l.address == pc { "=>" } else { "  " };
+                if l.is_code {
+                    format!("{} {}", marker, l)
+                } else {
+                    let byte = [memory[l.address] as u8];
+                    let data = disassemble(&byte, l.address, &[], state.platform());
+                    format!("{} {}", marker, data[0])
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// Bytes in rows of 16, each row starting with its address.
+fn hex_dump(address: usize, bytes: &[usize]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("{:#05X}: {}", address + row * 16, hex.join(" "))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Commands and their arguments are parsed, with short names and optional arguments.
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            Command::parse("b 0x2a4"),
+            Ok(Command::Break(Location::Address(0x2A4)))
+        );
+        assert_eq!(
+            Command::parse("b game.8o:12"),
+            Ok(Command::Break(Location::Line("game.8o".to_string(), 12)))
+        );
+        assert_eq!(Command::parse("step"), Ok(Command::Step(1)));
+        assert_eq!(Command::parse("s 10"), Ok(Command::Step(10)));
+        assert_eq!(
+            Command::parse("set v3 0xff"),
+            Ok(Command::Set(Register::V(3), 0xFF))
+        );
+        assert_eq!(Command::parse("mem 0x300"), Ok(Command::Memory(0x300, 16)));
+        assert_eq!(
+            Command::parse("w 0x300 1 0x2"),
+            Ok(Command::Write(0x300, vec![1, 2]))
+        );
+
+        assert_eq!(
+            Command::parse("watch vf"),
+            Ok(Command::Watch(vec![Watchpoint::Register(Register::V(0xF))]))
+        );
+        assert_eq!(
+            Command::parse("awatch 0x300 2"),
+            Ok(Command::Watch(vec![
+                Watchpoint::Read(0x300..0x302),
+                Watchpoint::Write(0x300..0x302)
+            ]))
+        );
+
+        assert_eq!(Command::parse("profile"), Ok(Command::Profile));
+        assert_eq!(Command::parse("profile stop"), Ok(Command::ProfileStop));
+
+        assert!(Command::parse("set v3 256").is_err());
+        assert!(Command::parse("watch q").is_err());
+        assert!(Command::parse("set q 1").is_err());
+        assert!(Command::parse("break").is_err());
+        assert!(Command::parse("w 0x300 0x100").is_err());
+        assert!(Command::parse("frobnicate").is_err());
+        assert!(Command::parse("mem 0xffffffffffffffff 2").is_err());
+        assert!(Command::parse("mem 0xffffffffffffffff").is_err());
+        assert!(Command::parse("watch 0xffffffffffffffff 2").is_err());
+        assert!(Command::parse("b game.8o:x").is_err());
+    }
+
+    /// A disassembly is kept while the program counter moves between instructions it found and
+    /// only data is written, and redone when an instruction is.
+    #[test]
+    fn test_disassembly() {
+        // 0x200 LOAD V0 0x05, 0x202 JUMP 0x200.
+        let mut state = Chip8::new(chip8_core::Platform::default());
+        state.load_program(&[0x60, 0x05, 0x12, 0x00]).unwrap();
+        state.set_register(Register::Pc, 0x202);
+        let disassembly = Disassembly::new(&state);
+        assert_eq!(
+            disassembly.listing(&state, 1, 1),
+            "   0x200  6005      LOAD V0, 0x05\n=> 0x202  1200      JUMP 0x200"
+        );
+
+        state.tick().unwrap();
+        assert!(disassembly.is_current(&state));
+        state.write_memory(0x204, &[0x81]).unwrap();
+        assert!(disassembly.is_current(&state));
+        assert_eq!(
+            disassembly.listing(&state, 0, 1),
+            "=> 0x200  6005      LOAD V0, 0x05"
+        );
+        state.set_register(Register::Pc, 0x202);
+        assert_eq!(
+            disassembly.listing(&state, 0, 2),
+            "=> 0x202  1200      JUMP 0x200\n   0x204  81        .byte 0x81  ; #......#"
+        );
+
+        state.set_register(Register::Pc, 0x201);
+        assert!(!disassembly.is_current(&state));
+        state.set_register(Register::Pc, 0x200);
+        state.write_memory(0x203, &[0x02]).unwrap();
+        assert!(!disassembly.is_current(&state));
+    }
+
+    /// Lines of the source file are found by its path or name, and listed around a line.
+    #[test]
+    fn test_source_file() {
+        let text = ": main\n  v0 := 1\n\n  v1 := 2\n  jump main";
+        let (_, map) = chip8_core::assemble_with_source_map(text).unwrap();
+        let source = SourceFile::new("roms/game.8o", text, map);
+
+        assert_eq!(source.address("game.8o", 2), Ok(0x200));
+        assert_eq!(source.address("roms/game.8o", 3), Ok(0x202));
+        assert!(source.address("other.8o", 2).is_err());
+        assert!(source.address("game.8o", 6).is_err());
+
+        assert_eq!(source.describe(4), "roms/game.8o:4  v1 := 2");
+        assert_eq!(
+            source.listing(4, 1, 2),
+            "      3  \n=>    4    v1 := 2\n      5    jump main"
+        );
+    }
+}
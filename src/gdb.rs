@@ -0,0 +1,534 @@
+use crate::debugger::Control;
+use chip8_core::{Chip8, Register};
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+/// The register file GDB sees, numbered in this order. Each is little-endian, a byte long, or two
+/// for I and PC.
+const REGISTERS: [Register; 21] = [
+    Register::V(0x0),
+    Register::V(0x1),
+    Register::V(0x2),
+    Register::V(0x3),
+    Register::V(0x4),
+    Register::V(0x5),
+    Register::V(0x6),
+    Register::V(0x7),
+    Register::V(0x8),
+    Register::V(0x9),
+    Register::V(0xA),
+    Register::V(0xB),
+    Register::V(0xC),
+    Register::V(0xD),
+    Register::V(0xE),
+    Register::V(0xF),
+    Register::I,
+    Register::Pc,
+    Register::Sp,
+    Register::DelayTimer,
+    Register::SoundTimer,
+];
+
+/// Describes `REGISTERS` to clients that ask for it with `qXfer:features:read`.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+/// Signals given in stop replies.
+const SIGINT: u8 = 2; // Interrupted by the client.
+pub const SIGILL: u8 = 4; // The opcode failed to run.
+pub const SIGTRAP: u8 = 5; // A breakpoint, watchpoint or single step.
+
+/// A GDB remote serial protocol stub, for attaching debugger frontends over TCP. One client at a
+/// time. The emulator pauses when it connects, and carries on when it detaches.
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    received: Vec<u8>, // Bytes from the client that aren't yet a whole packet.
+    breakpoints: BTreeSet<usize>,
+    is_running: bool, // Continuing at the client's request, so it's waiting for a stop reply.
+}
+
+impl GdbStub {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            client: None,
+            received: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            is_running: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Accept a client if none is connected, then handle whatever it has sent. Never blocks.
+    pub fn poll(&mut self, state: &mut Chip8) -> Control {
+        let mut control = Control::Continue;
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, _)) if stream.set_nonblocking(true).is_ok() => {
+                    self.client = Some(stream);
+                    self.received.clear();
+                    self.is_running = false;
+                    control = Control::Pause;
+                }
+                _ => return Control::Continue,
+            }
+        }
+
+        let mut buffer = [0; 4096];
+        while let Some(client) = self.client.as_mut() {
+            match client.read(&mut buffer) {
+                Ok(0) => return self.disconnect(),
+                Ok(n) => self.received.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(_) => return self.disconnect(),
+            }
+        }
+
+        while let Some(packet) = self.next_packet() {
+            control = match packet {
+                Packet::Interrupt if self.is_running => {
+                    self.is_running = false;
+                    self.send(&format!("S{:02x}", SIGINT));
+                    Control::Pause
+                }
+                Packet::Interrupt => control,
+                Packet::Command(command) => match self.handle(&command, state) {
+                    Control::Continue => control,
+                    other => other,
+                },
+            };
+            if self.client.is_none() {
+                break;
+            }
+        }
+        control
+    }
+
+    /// Check, after an instruction is run, whether the client's run should stop at a breakpoint.
+    pub fn should_stop(&mut self, state: &Chip8) -> bool {
+        if self.is_running && self.breakpoints.contains(&state.register(Register::Pc)) {
+            self.stop(SIGTRAP);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Tell the client its run has stopped, eg. at a watchpoint or an error.
+    pub fn stop(&mut self, signal: u8) {
+        if self.is_running {
+            self.is_running = false;
+            self.send(&format!("S{:02x}", signal));
+        }
+    }
+
+    /// Take the next packet, or interrupt, from the bytes received. Acknowledgements from the
+    /// client are skipped, and packets with a bad checksum are asked for again.
+    fn next_packet(&mut self) -> Option<Packet> {
+        loop {
+            let start = self.received.iter().position(|&b| b == b'$' || b == 0x03)?;
+            if self.received[start] == 0x03 {
+                self.received.drain(..=start);
+                return Some(Packet::Interrupt);
+            }
+
+            let end = self.received[start..].iter().position(|&b| b == b'#')? + start;
+            if self.received.len() < end + 3 {
+                return None;
+            }
+            let data: Vec<u8> = self.received[start + 1..end].to_vec();
+            let checksum = std::str::from_utf8(&self.received[end + 1..end + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            self.received.drain(..end + 3);
+
+            if checksum == Some(sum(&data)) {
+                self.write(b"+");
+                return Some(Packet::Command(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.write(b"-");
+        }
+    }
+
+    /// Run a command from the client, replying to it.
+    fn handle(&mut self, command: &str, state: &mut Chip8) -> Control {
+        let mut chars = command.chars();
+        let kind = chars.next().map(String::from).unwrap_or_default();
+        let args = chars.as_str();
+        let reply = match kind.as_str() {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => REGISTERS
+                .iter()
+                .map(|&r| register_hex(r, state.register(r)))
+                .collect(),
+            "G" => {
+                let mut hex = args;
+                for &register in &REGISTERS {
+                    let len = register_size(register) * 2;
+                    if let Some(value) = hex.get(..len).and_then(parse_le_hex) {
+                        state.set_register(register, value);
+                    }
+                    hex = hex.get(len..).unwrap_or("");
+                }
+                "OK".to_string()
+            }
+            "p" => match parse_hex(args).and_then(|n| REGISTERS.get(n)) {
+                Some(&register) => register_hex(register, state.register(register)),
+                None => "E00".to_string(),
+            },
+            "P" => {
+                let (n, value) = args.split_once('=').unwrap_or((args, ""));
+                match (
+                    parse_hex(n).and_then(|n| REGISTERS.get(n)),
+                    parse_le_hex(value),
+                ) {
+                    (Some(&register), Some(value)) => {
+                        state.set_register(register, value);
+                        "OK".to_string()
+                    }
+                    _ => "E00".to_string(),
+                }
+            }
+            "m" => match parse_address_length(args).and_then(|(address, len)| {
+                let end = address.checked_add(len)?;
+                state.memory().get(address..end)
+            }) {
+                Some(bytes) => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let (range, hex) = args.split_once(':').unwrap_or((args, ""));
+                let bytes = parse_bytes(hex);
+                match (parse_address_length(range), bytes) {
+                    (Some((address, len)), Some(bytes))
+                        if bytes.len() == len && address.checked_add(len).is_some() =>
+                    {
+                        match state.write_memory(address, &bytes) {
+                            Ok(_) => "OK".to_string(),
+                            Err(_) => "E01".to_string(),
+                        }
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            // Software breakpoints only.
+            "Z" | "z" => match args.strip_prefix("0,").and_then(|a| a.split(',').next()) {
+                Some(address) => match parse_hex(address) {
+                    Some(address) if kind == "Z" => {
+                        self.breakpoints.insert(address);
+                        "OK".to_string()
+                    }
+                    Some(address) => {
+                        self.breakpoints.remove(&address);
+                        "OK".to_string()
+                    }
+                    None => "E00".to_string(),
+                },
+                None => String::new(),
+            },
+            "s" => {
+                if let Some(address) = parse_hex(args) {
+                    state.set_register(Register::Pc, address);
+                }
+                // Step a whole tick, so waiting, tracing and watchpoints work as when running.
+                match state.tick() {
+                    Ok(_) => format!("S{:02x}", SIGTRAP),
+                    Err(_) => format!("S{:02x}", SIGILL),
+                }
+            }
+            "c" => {
+                if let Some(address) = parse_hex(args) {
+                    state.set_register(Register::Pc, address);
+                }
+                self.is_running = true;
+                return Control::Resume;
+            }
+            "D" => {
+                self.send("OK");
+                return self.disconnect();
+            }
+            "k" => return self.disconnect(),
+            "H" | "T" => "OK".to_string(),
+            "q" => self.query(args),
+            _ => String::new(), // Not supported.
+        };
+
+        self.send(&reply);
+        Control::Continue
+    }
+
+    /// Answer a general query, `q` followed by its name.
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return "PacketSize=4000;qXfer:features:read+".to_string();
+        }
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            return match parse_address_length(range) {
+                Some((offset, len)) => {
+                    let rest = TARGET_XML.get(offset.min(TARGET_XML.len())..).unwrap_or("");
+                    match rest.get(..len) {
+                        Some(part) if len < rest.len() => format!("m{}", part),
+                        _ => format!("l{}", rest),
+                    }
+                }
+                None => "E00".to_string(),
+            };
+        }
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Forget the client and its breakpoints, and carry on running.
+    fn disconnect(&mut self) -> Control {
+        self.client = None;
+        self.breakpoints.clear();
+        self.is_running = false;
+        Control::Resume
+    }
+
+    fn send(&mut self, data: &str) {
+        let packet = format!("${}#{:02x}", data, sum(data.as_bytes()));
+        self.write(packet.as_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let failed = match self.client.as_mut() {
+            Some(client) => write_all(client, bytes).is_err(),
+            None => false,
+        };
+        if failed {
+            self.disconnect();
+        }
+    }
+}
+
+/// What the client sent.
+enum Packet {
+    Command(String),
+    Interrupt, // Ctrl-C, sent as a lone 0x03 byte.
+}
+
+/// Write everything, waiting out a full socket buffer. The socket doesn't block.
+fn write_all(stream: &mut TcpStream, mut bytes: &[u8]) -> io::Result<()> {
+    while !bytes.is_empty() {
+        match stream.write(bytes) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => bytes = &bytes[n..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => {
+                std::thread::yield_now()
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// A packet's checksum: the sum of its bytes, modulo 256.
+fn sum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+fn register_size(register: Register) -> usize {
+    if register.max_value() > 0xFF {
+        2
+    } else {
+        1
+    }
+}
+
+/// A register's value as little-endian hex bytes.
+fn register_hex(register: Register, value: usize) -> String {
+    (0..register_size(register))
+        .map(|n| format!("{:02x}", (value >> (8 * n)) & 0xFF))
+        .collect()
+}
+
+fn parse_hex(hex: &str) -> Option<usize> {
+    usize::from_str_radix(hex, 16).ok()
+}
+
+/// Hex bytes, eg. `a2f0`.
+fn parse_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|n| u8::from_str_radix(hex.get(n..n + 2)?, 16).ok())
+        .collect()
+}
+
+/// A number from little-endian hex bytes, as registers are sent.
+fn parse_le_hex(hex: &str) -> Option<usize> {
+    let bytes = parse_bytes(hex)?;
+    Some(
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |value, &b| (value << 8) | b as usize),
+    )
+}
+
+/// `addr,length` in hex, as memory reads and writes give them.
+fn parse_address_length(args: &str) -> Option<(usize, usize)> {
+    let (address, len) = args.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(len)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::Platform;
+    use std::time::Duration;
+
+    /// Send a packet and wait for the reply, polling the stub as the emulator would.
+    fn request(
+        stub: &mut GdbStub,
+        state: &mut Chip8,
+        client: &mut TcpStream,
+        data: &str,
+    ) -> String {
+        let packet = format!("${}#{:02x}", data, sum(data.as_bytes()));
+        client.write_all(packet.as_bytes()).unwrap();
+        reply(stub, state, client)
+    }
+
+    /// Wait for the acknowledgement and reply to the last packet sent.
+    fn reply(stub: &mut GdbStub, state: &mut Chip8, client: &mut TcpStream) -> String {
+        let mut received = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            stub.poll(state);
+            if let Ok(n) = client.read(&mut buffer) {
+                received.extend_from_slice(&buffer[..n]);
+            }
+            let text = String::from_utf8_lossy(&received).into_owned();
+            if let Some(end) = text.find('#') {
+                if text.len() >= end + 3 {
+                    let start = text.find('$').unwrap();
+                    assert!(text.starts_with('+'));
+                    return text[start + 1..end].to_string();
+                }
+            }
+        }
+    }
+
+    /// A client on localhost reads and writes registers and memory, and steps and stops at
+    /// breakpoints.
+    #[test]
+    fn test_gdb_stub() {
+        // 0x200 LOAD V0 0x05, 0x202 LOADI 0x300, 0x204 ADD V0 1, 0x206 JUMP 0x204.
+        let mut state = Chip8::new(Platform::default());
+        state
+            .load_program(&[0x60, 0x05, 0xA3, 0x00, 0x70, 0x01, 0x12, 0x04])
+            .unwrap();
+
+        let mut stub = GdbStub::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(stub.local_addr().unwrap()).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+        while !stub.is_connected() {
+            stub.poll(&mut state);
+        }
+
+        let registers = request(&mut stub, &mut state, &mut client, "g");
+        assert_eq!(
+            registers,
+            format!("{}{}{}", "00".repeat(16), "0000", "0002000000")
+        );
+        assert_eq!(
+            request(&mut stub, &mut state, &mut client, "m200,4"),
+            "6005a300"
+        );
+
+        assert_eq!(request(&mut stub, &mut state, &mut client, "s"), "S05");
+        assert_eq!(request(&mut stub, &mut state, &mut client, "p0"), "05");
+        assert_eq!(request(&mut stub, &mut state, &mut client, "p11"), "0202");
+
+        assert_eq!(request(&mut stub, &mut state, &mut client, "P0=2a"), "OK");
+        assert_eq!(state.register(Register::V(0)), 0x2A);
+        assert_eq!(
+            request(&mut stub, &mut state, &mut client, "M300,2:abcd"),
+            "OK"
+        );
+        assert_eq!(&state.memory()[0x300..0x302], &[0xAB, 0xCD]);
+        assert_eq!(
+            request(&mut stub, &mut state, &mut client, "m1000,1"),
+            "E01"
+        );
+        assert_eq!(
+            request(&mut stub, &mut state, &mut client, "mffffffffffffffff,1"),
+            "E01"
+        );
+        assert_eq!(
+            request(&mut stub, &mut state, &mut client, "Mffffffffffffffff,1:00"),
+            "E01"
+        );
+
+        assert_eq!(
+            request(&mut stub, &mut state, &mut client, "Z0,206,2"),
+            "OK"
+        );
+        let packet = format!("$c#{:02x}", sum(b"c"));
+        client.write_all(packet.as_bytes()).unwrap();
+        while !stub.is_running {
+            stub.poll(&mut state);
+        }
+        while !stub.should_stop(&state) {
+            state.tick().unwrap();
+        }
+        assert_eq!(state.register(Register::Pc), 0x206);
+        assert_eq!(state.register(Register::I), 0x300);
+        assert_eq!(reply(&mut stub, &mut state, &mut client), "S05");
+
+        // 0x0000 isn't an opcode.
+        assert_eq!(request(&mut stub, &mut state, &mut client, "s400"), "S04");
+
+        assert_eq!(request(&mut stub, &mut state, &mut client, "D"), "OK");
+        assert!(!stub.is_connected());
+    }
+}
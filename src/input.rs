@@ -1,6 +1,8 @@
+use crate::keymap::{Control, Keymap};
+use sdl2::controller::GameController;
 use sdl2::event::Event;
-use sdl2::keyboard::{Keycode, Scancode};
-use sdl2::EventPump;
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use sdl2::{EventPump, GameControllerSubsystem};
 
 #[derive(PartialEq)]
 pub enum InputEvent {
@@ -10,38 +12,51 @@ pub enum InputEvent {
     Tick,
     SaveState,
     RestoreState,
+    SelectSlot(usize),
+    Confirm,
+    Rewind,
+    StopRewind,
+    ControllerAdded(String), // The controller's name.
+    ControllerRemoved(String),
 }
 
 pub struct Input {
     event_pump: EventPump,
+    controller_subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>, // Those plugged in, opened and closed as they come and go.
+    keymap: Keymap,
 }
 
 impl Input {
-    // Binding each of the Chip8's keys to a real keyboard key. Chip8 has 16 keys: 0-F. Each is an
-    // index in this array. See get_chip8_keys for details.
-    const KEY_BINDINGS: [Scancode; 16] = [
-        Scancode::X,
-        Scancode::Num1,
-        Scancode::Num2,
-        Scancode::Num3,
-        Scancode::Q,
-        Scancode::W,
-        Scancode::E,
-        Scancode::A,
-        Scancode::S,
-        Scancode::D,
-        Scancode::Z,
-        Scancode::C,
-        Scancode::Num4,
-        Scancode::R,
-        Scancode::F,
-        Scancode::V,
+    // Keys for choosing a save slot, with Ctrl held. Slot n is the nth key.
+    const SLOT_KEYS: [Keycode; 10] = [
+        Keycode::Num0,
+        Keycode::Num1,
+        Keycode::Num2,
+        Keycode::Num3,
+        Keycode::Num4,
+        Keycode::Num5,
+        Keycode::Num6,
+        Keycode::Num7,
+        Keycode::Num8,
+        Keycode::Num9,
     ];
 
-    pub fn new(context: &sdl2::Sdl) -> Result<Self, String> {
+    /// How far a stick or trigger is pushed before it counts as held, out of 32767.
+    const AXIS_THRESHOLD: i16 = 16384;
+
+    /// Controllers already plugged in are opened as SDL reports them added, like those plugged in
+    /// later.
+    pub fn new(context: &sdl2::Sdl, keymap: Keymap) -> Result<Self, String> {
         let event_pump = context.event_pump()?;
+        let controller_subsystem = context.game_controller()?;
 
-        Ok(Self { event_pump })
+        Ok(Self {
+            event_pump,
+            controller_subsystem,
+            controllers: Vec::new(),
+            keymap,
+        })
     }
 
     /// Return a single, highest priority event.
@@ -49,30 +64,66 @@ impl Input {
     /// state to the emulator.
     pub fn get_event(&mut self) -> InputEvent {
         let mut x = InputEvent::None;
+        let keymap = &self.keymap;
 
         for event in self.event_pump.poll_iter() {
             x = match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => InputEvent::Exit,
+                Event::Quit { .. } => InputEvent::Exit,
+                Event::KeyDown {
+                    scancode: Some(s), ..
+                } if s == keymap.quit => InputEvent::Exit,
                 Event::KeyUp {
-                    keycode: Some(Keycode::Space),
-                    ..
-                } => InputEvent::ToggleRun,
+                    scancode: Some(s), ..
+                } if s == keymap.pause => InputEvent::ToggleRun,
                 Event::KeyUp {
-                    keycode: Some(Keycode::F5),
+                    scancode: Some(s), ..
+                } if s == keymap.save => InputEvent::SaveState,
+                Event::KeyUp {
+                    scancode: Some(s), ..
+                } if s == keymap.load => InputEvent::RestoreState,
+                Event::KeyUp {
+                    scancode: Some(s), ..
+                } if s == keymap.step => InputEvent::Tick,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    repeat: false,
                     ..
-                } => InputEvent::SaveState,
+                } => InputEvent::Rewind,
                 Event::KeyUp {
-                    keycode: Some(Keycode::F9),
+                    keycode: Some(Keycode::Backspace),
                     ..
-                } => InputEvent::RestoreState,
+                } => InputEvent::StopRewind,
                 Event::KeyUp {
-                    keycode: Some(Keycode::Right),
+                    keycode: Some(Keycode::Return),
+                    ..
+                } => InputEvent::Confirm,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
                     ..
-                } => InputEvent::Tick,
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    match Self::SLOT_KEYS.iter().position(|&k| k == keycode) {
+                        Some(slot) => InputEvent::SelectSlot(slot),
+                        None => InputEvent::None,
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match self.controller_subsystem.open(which) {
+                        Ok(controller) => {
+                            let name = controller.name();
+                            self.controllers.push(controller);
+                            InputEvent::ControllerAdded(name)
+                        }
+                        Err(_) => InputEvent::None,
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    let id = which as i32;
+                    match self.controllers.iter().position(|c| c.instance_id() == id) {
+                        Some(n) => InputEvent::ControllerRemoved(self.controllers.remove(n).name()),
+                        None => InputEvent::None,
+                    }
+                }
                 Event::KeyDown { .. } => InputEvent::None,
                 _ => InputEvent::None,
             };
@@ -82,7 +133,7 @@ impl Input {
             }
         }
 
-        return x;
+        x
     }
 
     /// Get The state of the 16 input keys Chip8 has.
@@ -108,15 +159,26 @@ impl Input {
             .pressed_scancodes()
             .collect();
 
-        // Hard coded binding of keyboard to keys.  We use the left 16 keys in the same grid pattern
-        // which means none of the letters/numbers align, but the shape does.
-        let key_states = Self::KEY_BINDINGS
-            .iter()
-            .map(|b| keys.contains(b))
-            .collect::<Vec<bool>>();
+        // Keys are bound by the keymap. See Keymap::default for the usual layout.
+        let mut chip8_keys = [false; 16];
+        for (held, scancode) in chip8_keys.iter_mut().zip(self.keymap.chip8_keys.iter()) {
+            *held = keys.contains(scancode);
+        }
+
+        for controller in &self.controllers {
+            for &(control, key) in &self.keymap.controller {
+                chip8_keys[key] |= is_held(controller, control);
+            }
+        }
+        chip8_keys
+    }
+}
 
-        let mut foo = [false; 16];
-        foo.copy_from_slice(&key_states[..]);
-        return foo;
+/// Whether a control is held down on a controller.
+fn is_held(controller: &GameController, control: Control) -> bool {
+    match control {
+        Control::Button(button) => controller.button(button),
+        Control::AxisNegative(axis) => controller.axis(axis) < -Input::AXIS_THRESHOLD,
+        Control::AxisPositive(axis) => controller.axis(axis) > Input::AXIS_THRESHOLD,
     }
 }
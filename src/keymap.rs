@@ -0,0 +1,301 @@
+use sdl2::controller::{Axis, Button};
+use sdl2::keyboard::Scancode;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use toml::value::Table;
+
+/// Which keyboard keys press the Chip8's keys and the emulator's hotkeys, and which game
+/// controller buttons and stick directions press the Chip8's keys.
+///
+/// The defaults can be changed by a global config file, `~/.config/chip8/keymap.toml`, and then
+/// by one for a ROM next to it, eg. `roms/TETRIS.keymap.toml`. Either file only needs the keys it
+/// changes:
+///
+/// ```toml
+/// [keys]      # Chip8 keys, 0-F.
+/// 2 = "Up"
+/// 8 = "Down"
+///
+/// [hotkeys]   # pause, step, save, load and quit.
+/// pause = "P"
+///
+/// [controller]
+/// b = "6"
+/// "+righty" = "8"
+/// ```
+///
+/// Keys are named as SDL names them, eg. "X", "1", "Space", "Left Shift", "Keypad 8". So are
+/// controller buttons, eg. "a", "dpup", "start", with stick directions and triggers as SDL's
+/// mapping strings write them, eg. "-leftx", "+lefty", "righttrigger".
+pub struct Keymap {
+    pub chip8_keys: [Scancode; 16], // The key for each Chip8 key, 0-F.
+    pub pause: Scancode,
+    pub step: Scancode, // Run one instruction while paused.
+    pub save: Scancode,
+    pub load: Scancode,
+    pub quit: Scancode,
+    pub controller: Vec<(Control, usize)>, // Controls and the Chip8 key each presses.
+}
+
+/// A game controller button, or a stick or trigger pushed one way.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Control {
+    Button(Button),
+    AxisNegative(Axis), // Left or up.
+    AxisPositive(Axis), // Right or down, or a trigger pulled.
+}
+
+impl Control {
+    /// A control by its name in SDL's mapping strings. Sticks need a direction, eg. "-leftx", and
+    /// triggers don't.
+    fn parse(name: &str) -> Option<Self> {
+        if let Some(axis) = name.strip_prefix('-') {
+            Axis::from_string(axis).map(Control::AxisNegative)
+        } else if let Some(axis) = name.strip_prefix('+') {
+            Axis::from_string(axis).map(Control::AxisPositive)
+        } else {
+            match Axis::from_string(name) {
+                Some(Axis::TriggerLeft) => Some(Control::AxisPositive(Axis::TriggerLeft)),
+                Some(Axis::TriggerRight) => Some(Control::AxisPositive(Axis::TriggerRight)),
+                Some(_) => None,
+                None => Button::from_string(name).map(Control::Button),
+            }
+        }
+    }
+}
+
+impl Keymap {
+    const FILE_NAME: &'static str = "keymap.toml";
+
+    /// The defaults, changed by the global config file and then the ROM's, if they exist.
+    pub fn load(rom_path: &str) -> Result<Self, String> {
+        let mut keymap = Keymap::default();
+        let rom_config = PathBuf::from(format!("{}.{}", rom_path, Keymap::FILE_NAME));
+        for path in global_config().into_iter().chain(Some(rom_config)) {
+            match fs::read_to_string(&path) {
+                Ok(text) => keymap
+                    .apply(&text)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?,
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// Change the keys a config file sets. Anything it doesn't know is an error, so a typo
+    /// doesn't quietly leave a key as it was.
+    fn apply(&mut self, text: &str) -> Result<(), String> {
+        let config: Table = toml::from_str(text).map_err(|e| e.to_string())?;
+        for (section, entries) in &config {
+            if !["keys", "hotkeys", "controller"].contains(&section.as_str()) {
+                return Err(format!(
+                    "{}: sections are [keys], [hotkeys] and [controller]",
+                    section
+                ));
+            }
+            let entries = entries
+                .as_table()
+                .ok_or_else(|| format!("{}: expected a table, [{}]", section, section))?;
+
+            for (name, value) in entries {
+                let field = format!("{}.{}", section, name);
+                let result = match (section.as_str(), value.as_str()) {
+                    ("controller", Some(key)) => self.bind_control(name, key),
+                    ("controller", None) => Err("expected a Chip8 key in quotes".to_string()),
+                    (_, Some(key)) => self.bind_key(section, name, key),
+                    (_, None) => Err("expected a key name in quotes".to_string()),
+                };
+                result.map_err(|e| format!("{}: {}", field, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Bind a keyboard key, by its SDL name, to a Chip8 key or hotkey.
+    fn bind_key(&mut self, section: &str, name: &str, key: &str) -> Result<(), String> {
+        let scancode = Scancode::from_name(key).ok_or_else(|| {
+            format!(
+                "unknown key name \"{}\". Names are SDL's, eg. \"X\", \"Space\", \
+                 \"Left Shift\", \"Keypad 8\".",
+                key
+            )
+        })?;
+        let unknown = match section {
+            "keys" => "Chip8 keys are 0-F",
+            _ => "hotkeys are pause, step, save, load and quit",
+        };
+        *self.binding(section, name).ok_or(unknown)? = scancode;
+        Ok(())
+    }
+
+    /// Bind a controller button or stick direction, by its SDL name, to a Chip8 key.
+    fn bind_control(&mut self, name: &str, key: &str) -> Result<(), String> {
+        let control = Control::parse(name).ok_or(
+            "unknown control. Controls are SDL's, eg. \"a\", \"dpup\", \"start\", \
+             \"righttrigger\", or a stick direction, eg. \"-leftx\".",
+        )?;
+        let key = chip8_key(key).ok_or("Chip8 keys are 0-F")?;
+        self.controller.retain(|&(c, _)| c != control);
+        self.controller.push((control, key));
+        Ok(())
+    }
+
+    /// The key bound to a Chip8 key, by its hex digit, or to a hotkey by its name.
+    fn binding(&mut self, section: &str, name: &str) -> Option<&mut Scancode> {
+        match section {
+            "keys" => Some(&mut self.chip8_keys[chip8_key(name)?]),
+            "hotkeys" => match name {
+                "pause" => Some(&mut self.pause),
+                "step" => Some(&mut self.step),
+                "save" => Some(&mut self.save),
+                "load" => Some(&mut self.load),
+                "quit" => Some(&mut self.quit),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Default for Keymap {
+    /// The keyboard's left four columns laid out as the Chip8 keypad, so none of the letters or
+    /// numbers line up but the shape does.
+    ///
+    /// ```text
+    /// 1 2 3 4      1 2 3 C
+    /// Q W E R  ->  4 5 6 D
+    /// A S D F      7 8 9 E
+    /// Z X C V      A 0 B F
+    /// ```
+    ///
+    /// On a controller, the D-pad and left stick are 2, 4, 6 and 8, which most games use as
+    /// arrows, and A is 5.
+    fn default() -> Self {
+        Self {
+            chip8_keys: [
+                Scancode::X,
+                Scancode::Num1,
+                Scancode::Num2,
+                Scancode::Num3,
+                Scancode::Q,
+                Scancode::W,
+                Scancode::E,
+                Scancode::A,
+                Scancode::S,
+                Scancode::D,
+                Scancode::Z,
+                Scancode::C,
+                Scancode::Num4,
+                Scancode::R,
+                Scancode::F,
+                Scancode::V,
+            ],
+            pause: Scancode::Space,
+            step: Scancode::Right,
+            save: Scancode::F5,
+            load: Scancode::F9,
+            quit: Scancode::Escape,
+            controller: vec![
+                (Control::Button(Button::DPadUp), 0x2),
+                (Control::Button(Button::DPadLeft), 0x4),
+                (Control::Button(Button::DPadRight), 0x6),
+                (Control::Button(Button::DPadDown), 0x8),
+                (Control::AxisNegative(Axis::LeftY), 0x2),
+                (Control::AxisNegative(Axis::LeftX), 0x4),
+                (Control::AxisPositive(Axis::LeftX), 0x6),
+                (Control::AxisPositive(Axis::LeftY), 0x8),
+                (Control::Button(Button::A), 0x5),
+            ],
+        }
+    }
+}
+
+/// A Chip8 key by its hex digit.
+fn chip8_key(name: &str) -> Option<usize> {
+    match name.len() {
+        1 => usize::from_str_radix(name, 16).ok(),
+        _ => None,
+    }
+}
+
+/// `~/.config/chip8/keymap.toml`, or under `$XDG_CONFIG_HOME` if it's set.
+fn global_config() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("chip8").join(Keymap::FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Controls are rebound rather than bound twice, and sticks need a direction.
+    #[test]
+    fn test_controller() {
+        let mut keymap = Keymap::default();
+        keymap
+            .apply("[controller]\na = \"6\"\n\"+righty\" = \"8\"\nrighttrigger = \"f\"")
+            .unwrap();
+        let bound = |control| {
+            keymap
+                .controller
+                .iter()
+                .filter(|&&(c, _)| c == control)
+                .map(|&(_, key)| key)
+                .collect::<Vec<usize>>()
+        };
+        assert_eq!(bound(Control::Button(Button::A)), vec![0x6]);
+        assert_eq!(bound(Control::AxisPositive(Axis::RightY)), vec![0x8]);
+        assert_eq!(bound(Control::AxisPositive(Axis::TriggerRight)), vec![0xF]);
+        assert_eq!(bound(Control::Button(Button::DPadUp)), vec![0x2]);
+
+        assert!(keymap
+            .apply("[controller]\nleftx = \"4\"")
+            .unwrap_err()
+            .starts_with("controller.leftx: unknown control."));
+        assert_eq!(
+            keymap.apply("[controller]\nb = \"G\"").unwrap_err(),
+            "controller.b: Chip8 keys are 0-F"
+        );
+    }
+
+    /// A config changes only the keys it names, and bad names are errors saying where they are.
+    #[test]
+    fn test_apply() {
+        let mut keymap = Keymap::default();
+        keymap
+            .apply("[keys]\n2 = \"Up\"\nf = \"keypad 8\"\n[hotkeys]\npause = \"P\"")
+            .unwrap();
+        assert!(keymap.chip8_keys[0x2] == Scancode::Up);
+        assert!(keymap.chip8_keys[0xF] == Scancode::Kp8);
+        assert!(keymap.chip8_keys[0x1] == Scancode::Num1);
+        assert!(keymap.pause == Scancode::P);
+        assert!(keymap.quit == Scancode::Escape);
+
+        assert_eq!(
+            keymap.apply("[hotkeys]\npause = \"Spcae\"").unwrap_err(),
+            "hotkeys.pause: unknown key name \"Spcae\". Names are SDL's, eg. \"X\", \"Space\", \
+             \"Left Shift\", \"Keypad 8\"."
+        );
+        assert_eq!(
+            keymap.apply("[keys]\n10 = \"A\"").unwrap_err(),
+            "keys.10: Chip8 keys are 0-F"
+        );
+        assert_eq!(
+            keymap.apply("[hotkeys]\nrewind = \"A\"").unwrap_err(),
+            "hotkeys.rewind: hotkeys are pause, step, save, load and quit"
+        );
+        assert_eq!(
+            keymap.apply("[key]\n1 = \"A\"").unwrap_err(),
+            "key: sections are [keys], [hotkeys] and [controller]"
+        );
+        assert_eq!(
+            keymap.apply("[keys]\n1 = 1").unwrap_err(),
+            "keys.1: expected a key name in quotes"
+        );
+    }
+}
@@ -1,97 +1,396 @@
 mod audio;
-mod chip8;
+mod dashboard;
+mod debugger;
+mod gdb;
 mod input;
+mod keymap;
 use audio::Audio;
 mod screen;
-use chip8::Chip8;
-use console::Term;
+mod slots;
+use chip8_core::{
+    assemble, assemble_with_source_map, compare_trace, disassemble, parse_number,
+    parse_trace_range, Chip8, Chip8Error, FramePacer, Rewind, RomOptions, StepOutcome,
+    TraceComparison, TraceEntry, TraceState,
+};
+use debugger::{Control, Debugger, SourceFile};
+use gdb::GdbStub;
 use input::{Input, InputEvent};
+use keymap::Keymap;
 use screen::Screen;
+use slots::SaveSlot;
 use std::env;
-use std::thread::sleep;
-use std::time::Duration;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::process;
 
 struct Emulator {
     debugger: Debugger,
     input: Input,
+    instructions_per_frame: usize,
     screen: Screen,
     audio: Audio,
+    rom_path: String,
+    rpl_path: String,
+    slot: usize,                                // Save slot F5 and the slot picker use.
+    slot_picker: Option<Vec<Option<SaveSlot>>>, // The slots, while the picker is open.
     state: Chip8,
-    saved_state: Option<Chip8>,
+    rewind: Rewind,
     is_paused: bool,
+    is_rewinding: bool,
+    trace: Option<BufWriter<File>>, // The --trace log, written after each frame or step.
+    profile_path: Option<String>,   // Where to write the profile on exit.
+    coverage_paths: Vec<String>,    // Where to write the coverage report and heatmap on exit.
+    gdb: Option<GdbStub>,           // Listening for a GDB client, with --gdb.
 }
 
 impl Emulator {
     const SCREEN_ZOOM: u32 = 20; // Multiple to zoom screen by.
     const TONE: u32 = 440; // Pitch for beep sound.
+    const REWIND_FRAMES: usize = 600; // How far back rewinding can go, in frames.
+
+    fn new(options: &Options) -> Result<Self, String> {
+        let path = &options.rom.path;
+        let platform = options.rom.platform;
 
-    fn new(path: &String) -> Result<Self, String> {
         // CLI debugging.
-        let debugger = Debugger::new();
+        let mut debugger = Debugger::new();
 
         // SDL-based I/O.
         let sdl_context = sdl2::init()?;
-        let input = Input::new(&sdl_context)?;
+        let input = Input::new(&sdl_context, Keymap::load(path)?)?;
         let screen = Screen::new(&sdl_context, Emulator::SCREEN_ZOOM)?;
         let audio = Audio::new(Emulator::TONE);
 
         // The emulated Chip8 state. This includes memory, registers, counters, timers, etc.
-        let mut state = Chip8::new();
-        state.load_rom(path).unwrap();
+        let mut state = Chip8::new(platform);
+        if path.ends_with(".8o") {
+            // Octo source is assembled and run, with the debugger showing its lines.
+            let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            let (program, source_map) =
+                assemble_with_source_map(&text).map_err(|e| format!("{}: {}", path, e))?;
+            state.load_program(&program).map_err(|e| e.to_string())?;
+            debugger.set_source(SourceFile::new(path, &text, source_map));
+        } else {
+            state.load_rom(path).map_err(|e| e.to_string())?;
+        }
+
+        // SUPER-CHIP user flags persist between runs of the same ROM.
+        let rpl_path = format!("{}.rpl", path);
+        if let Ok(bytes) = fs::read(&rpl_path) {
+            for (flag, &value) in state.rpl_flags.iter_mut().zip(bytes.iter()) {
+                *flag = value as usize;
+            }
+        }
+
+        let trace = match &options.trace {
+            Some(trace_path) => {
+                state.start_trace(options.trace_range.clone());
+                let mut file = File::create(trace_path)
+                    .map(BufWriter::new)
+                    .map_err(|e| format!("{}: {}", trace_path, e))?;
+                writeln!(file, "{}", TraceEntry::HEADER).map_err(|e| e.to_string())?;
+                Some(file)
+            }
+            None => None,
+        };
+
+        if options.profile.is_some() {
+            state.start_profile();
+        }
+        if !options.coverage.is_empty() {
+            state.start_coverage();
+        }
+
+        let gdb = match options.gdb {
+            Some(port) => {
+                let stub = GdbStub::bind(("127.0.0.1", port))
+                    .map_err(|e| format!("Could not listen for GDB on port {}: {}", port, e))?;
+                let address = stub.local_addr().map_err(|e| e.to_string())?;
+                debugger.write(format!("Listening for GDB on {}", address));
+                Some(stub)
+            }
+            None => None,
+        };
 
+        debugger.write(format!("Platform: {}", platform));
         debugger.write(state.dum_loaded_rom());
+        debugger.write("Type help at the prompt for debugger commands.".to_string());
 
         Ok(Self {
             debugger,
             input,
+            instructions_per_frame: options.rom.instructions_per_frame,
             screen,
             state,
             audio,
-            saved_state: None,
+            rom_path: path.clone(),
+            rpl_path,
+            slot: 0,
+            slot_picker: None,
+            rewind: Rewind::new(Emulator::REWIND_FRAMES),
             is_paused: false,
+            is_rewinding: false,
+            trace,
+            profile_path: options.profile.clone(),
+            coverage_paths: options.coverage.clone(),
+            gdb,
         })
     }
 
+    fn save_rpl_flags(&mut self) {
+        // Only bother writing the file if the ROM has used the flags.
+        if self.state.rpl_flags.iter().any(|&f| f != 0) {
+            let bytes: Vec<u8> = self.state.rpl_flags.iter().map(|&f| f as u8).collect();
+            if let Err(e) = fs::write(&self.rpl_path, bytes) {
+                self.debugger
+                    .write(format!("Could not save RPL flags: {}", e));
+            }
+        }
+    }
+
+    /// Write the profile report to the --profile file, if profiling is still on.
+    fn save_profile(&mut self) {
+        if let (Some(path), Some(profile)) = (&self.profile_path, self.state.profile()) {
+            if let Err(e) = fs::write(path, profile.report(20)) {
+                self.debugger
+                    .write(format!("Could not save profile: {}", e));
+            }
+        }
+    }
+
+    /// Write the coverage report to each --coverage file, as a heatmap for `.png` files.
+    fn save_coverage(&mut self) {
+        if let Some(coverage) = self.state.coverage() {
+            for path in &self.coverage_paths {
+                let report = if path.ends_with(".png") {
+                    coverage.heatmap()
+                } else {
+                    coverage.report().into_bytes()
+                };
+                if let Err(e) = fs::write(path, report) {
+                    self.debugger
+                        .write(format!("Could not save coverage: {}", e));
+                }
+            }
+        }
+    }
+
+    /// Save the Chip8 to the selected slot's file next to the ROM.
     fn save_state(&mut self) {
-        self.saved_state = Some(self.state.clone());
+        let path = SaveSlot::path(&self.rom_path, self.slot);
+        match SaveSlot::new(&self.state).save(&path) {
+            Ok(_) => self
+                .debugger
+                .write(format!("Saved slot {} to {}", self.slot, path)),
+            Err(e) => self.debugger.write(format!("Could not save state: {}", e)),
+        }
     }
 
+    /// Restore the Chip8 from the selected slot, if it holds a valid save for this ROM.
     fn restore_state(&mut self) {
-        match &self.saved_state {
-            Some(s) => self.state = s.clone(),
-            None => (),
+        let result = SaveSlot::load(&SaveSlot::path(&self.rom_path, self.slot)).and_then(|slot| {
+            self.state
+                .restore_snapshot(&slot.snapshot)
+                .map_err(|e| e.to_string())
+        });
+
+        if let Err(e) = result {
+            self.debugger.write(format!("Could not load state: {}", e));
+        }
+    }
+
+    /// Read every slot and show them on screen, pausing emulation until a slot is loaded or the
+    /// picker is closed.
+    fn open_slot_picker(&mut self) {
+        let slots: Vec<Option<SaveSlot>> = (0..SaveSlot::COUNT)
+            .map(|n| SaveSlot::load(&SaveSlot::path(&self.rom_path, n)).ok())
+            .collect();
+        self.screen.draw_slot_picker(&slots, self.slot);
+        self.slot_picker = Some(slots);
+    }
+
+    /// Go back to showing the Chip8's display.
+    fn close_slot_picker(&mut self) {
+        self.slot_picker = None;
+        self.state.has_graphics_update = true;
+    }
+
+    /// Go back a frame through the rewind buffer. Rewinding stops at the oldest frame kept.
+    fn step_back(&mut self) {
+        match self.rewind.step_back(&mut self.state) {
+            Ok(true) => (),
+            Ok(false) => self.is_rewinding = false,
+            Err(e) => {
+                self.is_rewinding = false;
+                self.debugger.write(format!("Could not rewind: {}", e));
+            }
+        }
+    }
+
+    /// Whether a GDB client's run should stop at a breakpoint before the next instruction.
+    fn gdb_should_stop(&mut self) -> bool {
+        match self.gdb.as_mut() {
+            Some(gdb) => gdb.should_stop(&self.state),
+            None => false,
         }
     }
 
-    /// Loop forever at 500Hz.
-    /// Handles input, ticks the Chip8 CPU, draws graphics and plays audio.
+    /// Run a frame of instructions, then end the frame. Stops early, pausing emulation, if the
+    /// debugger stops the run, a watchpoint is touched or the Chip8 fails.
+    fn run_frame(&mut self) {
+        self.state.set_keys(self.input.get_chip8_keys());
+
+        for _ in 0..self.instructions_per_frame {
+            match self.state.tick() {
+                Ok(StepOutcome::Executed) if self.gdb_should_stop() => {
+                    self.is_paused = true;
+                    break;
+                }
+                Ok(StepOutcome::Executed) if self.debugger.should_stop(&self.state) => {
+                    self.is_paused = true;
+                    break;
+                }
+                Ok(StepOutcome::Watchpoint) => {
+                    if let Some(hit) = self.state.take_watch_hit() {
+                        self.debugger.stop_at_watchpoint(hit, &self.state);
+                    }
+                    if let Some(gdb) = self.gdb.as_mut() {
+                        gdb.stop(gdb::SIGTRAP);
+                    }
+                    self.is_paused = true;
+                    break;
+                }
+                Ok(StepOutcome::Exited) => break,
+                Ok(_) => (),
+                Err(e) => {
+                    if let Some(gdb) = self.gdb.as_mut() {
+                        gdb.stop(gdb::SIGILL);
+                    }
+                    self.show_error(e);
+                    break;
+                }
+            }
+        }
+
+        self.state.end_frame();
+        self.write_trace();
+    }
+
+    /// Write the instructions traced this frame to the --trace log. Tracing stops if it fails.
+    fn write_trace(&mut self) {
+        let entries = self.state.take_trace();
+        if let Some(file) = self.trace.as_mut() {
+            let result = entries
+                .iter()
+                .try_for_each(|entry| writeln!(file, "{}", entry));
+            if let Err(e) = result {
+                self.debugger.write(format!("Stopped tracing: {}", e));
+                self.state.stop_trace();
+                self.trace = None;
+            }
+        }
+    }
+
+    /// Pause emulation and show why the Chip8 could not continue.
+    /// It stays paused on the failed opcode, so the state can be inspected or a save restored.
+    fn show_error(&mut self, error: Chip8Error) {
+        self.is_paused = true;
+        self.debugger.write(format!("Paused. {}", error));
+    }
+
+    /// Loop forever at 60hz.
+    /// Each frame handles input, runs a frame of Chip8 instructions, draws graphics and plays
+    /// audio.
     pub fn run_forever(&mut self) {
+        let mut pacer = FramePacer::start();
+
         'program: loop {
             // Emulator and Chip8 I/O.
             match self.input.get_event() {
                 InputEvent::Exit => break 'program,
                 InputEvent::ToggleRun => self.is_paused = !self.is_paused,
                 InputEvent::SaveState => self.save_state(),
-                InputEvent::RestoreState => {
+                InputEvent::RestoreState => match self.slot_picker {
+                    Some(_) => self.close_slot_picker(),
+                    None => self.open_slot_picker(),
+                },
+                InputEvent::SelectSlot(slot) => {
+                    self.slot = slot;
+                    match &self.slot_picker {
+                        Some(slots) => self.screen.draw_slot_picker(slots, slot),
+                        None => self.debugger.write(format!("Selected slot {}", slot)),
+                    }
+                }
+                InputEvent::Confirm if self.slot_picker.is_some() => {
                     self.restore_state();
-                    self.screen.draw(&self.state.graphics_buffer);
-                    self.debugger.overwrite(self.state.dump_state());
+                    self.close_slot_picker();
                 }
+                InputEvent::Rewind => self.is_rewinding = true,
+                InputEvent::StopRewind => self.is_rewinding = false,
+                InputEvent::ControllerAdded(name) => self
+                    .debugger
+                    .write(format!("Controller connected: {}", name)),
+                InputEvent::ControllerRemoved(name) => self
+                    .debugger
+                    .write(format!("Controller disconnected: {}", name)),
                 InputEvent::Tick => {
-                    self.state.tick();
-                    self.debugger.overwrite(self.state.dump_state());
+                    if let Err(e) = self.state.tick() {
+                        self.show_error(e);
+                    }
+                    self.write_trace();
                 }
                 _ => (),
             }
 
-            if !self.is_paused {
-                self.state.set_keys(self.input.get_chip8_keys());
-                self.state.tick();
-                // debugger.overwrite(self.state.dump_state());
+            while let Some(line) = self.debugger.next_command(&self.state) {
+                match self.debugger.run_command(&line, &mut self.state) {
+                    Control::Pause => self.is_paused = true,
+                    Control::Resume => self.is_paused = false,
+                    Control::Continue => (),
+                }
+            }
+
+            if let Some(gdb) = self.gdb.as_mut() {
+                let was_connected = gdb.is_connected();
+                match gdb.poll(&mut self.state) {
+                    Control::Pause => self.is_paused = true,
+                    Control::Resume => self.is_paused = false,
+                    Control::Continue => (),
+                }
+                match (was_connected, gdb.is_connected()) {
+                    (false, true) => self.debugger.write("GDB attached.".to_string()),
+                    (true, false) => self.debugger.write("GDB detached.".to_string()),
+                    _ => (),
+                }
+            }
+
+            // Rewinding plays frames backwards, paused or not. Each frame run is recorded to rewind.
+            if self.slot_picker.is_some() {
+                // Everything waits on the slot picker.
+            } else if self.is_rewinding {
+                self.step_back();
+            } else if !self.is_paused {
+                self.run_frame();
+                self.rewind.push(&self.state);
+            }
+
+            if self.state.has_exited {
+                break 'program;
+            }
+
+            if self.state.has_graphics_update && self.slot_picker.is_none() {
+                self.screen
+                    .draw(&self.state.graphics_buffer, self.state.display_size());
+                self.state.has_graphics_update = false;
             }
+            self.debugger.draw(&self.state);
 
-            if self.state.has_graphics_update {
-                self.screen.draw(&self.state.graphics_buffer);
+            if self.state.has_audio_update {
+                self.audio
+                    .set_pattern(&self.state.audio_pattern, self.state.audio_rate());
+                self.state.has_audio_update = false;
             }
 
             if self.state.sound_timer > 0 && self.audio.is_paused() {
@@ -100,44 +399,183 @@ impl Emulator {
                 self.audio.stop();
             }
 
-            // Sleep at a rate that emulates about 500Hz. This won't be accurate.
-            sleep(Duration::new(0, 2_000_000 as u32))
+            pacer.wait();
         }
+
+        self.write_trace();
+        self.save_rpl_flags();
+        self.save_profile();
+        self.save_coverage();
     }
 }
 
-struct Debugger {
-    terminal: Term,
+const USAGE: &str = "USAGE: chip8 <rom-file | source-file.8o> [options]
+       chip8 disasm <rom-file> [--platform <name>]
+       chip8 asm <source-file> [--output <rom-file>]
+       chip8 compare <rom-file> --reference <trace-file> [--platform <name>] [--ipf <n>]
+                     [--seed <n>]
+
+Subcommands:
+    disasm                                Print the ROM's disassembly instead of running it.
+    asm                                   Assemble Octo source into a ROM (default <source>.ch8).
+    compare                               Run the ROM against a reference trace, a line per
+                                          instruction: PC, opcode, V0-VF and I in hex. Stops at
+                                          the first difference.
+
+Options:
+    --platform <vip|chip48|schip|xochip>  Interpreter to emulate (default vip).
+    --ipf <n>                             Instructions per frame (default depends on platform).
+    --trace <file>                        Log every instruction run, with the registers it changed.
+    --trace-range <start>-<end>           Only log instructions at these addresses, eg. 0x200-0x2ff.
+    --profile <file>                      Write where the cycles went on exit. The debugger's
+                                          profile command shows it while running.
+    --coverage <file>                     Write which bytes of the ROM were run as code, read as
+                                          data, or never reached, on exit. A PNG heatmap if it
+                                          ends in .png. Give it twice for both.
+    --gdb <port>                          Listen on 127.0.0.1:port for a GDB remote protocol client.
+    --output <file>                       Where asm writes the ROM.
+    --reference <file>                    The trace compare checks the ROM against.
+    --seed <n>                            Seed for RAND in compare (default 0).";
+
+struct Options {
+    rom: RomOptions,
+    trace: Option<String>,
+    trace_range: Range<usize>,
+    profile: Option<String>,
+    coverage: Vec<String>,
+    gdb: Option<u16>,
+    output: Option<String>,
+    reference: Option<String>,
+    seed: u64,
 }
 
-impl Debugger {
-    pub fn new() -> Self {
-        let terminal = Term::stdout();
-        Self { terminal }
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let (rom, flags) = RomOptions::parse(args)?;
+        let mut options = Self {
+            rom,
+            trace: None,
+            trace_range: 0..0x10000,
+            profile: None,
+            coverage: Vec::new(),
+            gdb: None,
+            output: None,
+            reference: None,
+            seed: 0,
+        };
+
+        for (flag, value) in &flags {
+            match flag.as_str() {
+                "--trace" => options.trace = Some(value.clone()),
+                "--trace-range" => options.trace_range = parse_trace_range(value)?,
+                "--profile" => options.profile = Some(value.clone()),
+                "--coverage" => options.coverage.push(value.clone()),
+                "--gdb" => {
+                    options.gdb = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("--gdb expects a port, not '{}'.", value))?,
+                    )
+                }
+                "--output" => options.output = Some(value.clone()),
+                "--reference" => options.reference = Some(value.clone()),
+                "--seed" => options.seed = parse_number(flag, value)?,
+                _ => return Err(format!("Unknown option {}.", flag)),
+            }
+        }
+
+        Ok(options)
     }
+}
 
-    pub fn write(&self, string: String) {
-        self.terminal.write_line(string.as_str()).unwrap();
+/// Print a disassembly of the ROM, telling its code from data by tracing it from 0x200.
+fn disassemble_rom(options: &Options) -> Result<(), String> {
+    let path = &options.rom.path;
+    let rom = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    for line in disassemble(&rom, 0x200, &[0x200], options.rom.platform) {
+        println!("{}", line);
     }
+    Ok(())
+}
 
-    pub fn overwrite(&self, string: String) {
-        let count = string.lines().count();
-        self.terminal.clear_last_lines(count + 1).unwrap();
-        self.write(string);
+/// Assemble the Octo source file into a ROM, next to it unless `--output` says otherwise.
+fn assemble_source(options: &Options) -> Result<(), String> {
+    let path = &options.rom.path;
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let program = assemble(&source).map_err(|e| format!("{}: {}", path, e))?;
+
+    let output = options.output.clone().unwrap_or_else(|| {
+        Path::new(path)
+            .with_extension("ch8")
+            .to_string_lossy()
+            .into_owned()
+    });
+    fs::write(&output, &program).map_err(|e| format!("{}: {}", output, e))?;
+    println!("Wrote {} bytes to {}.", program.len(), output);
+    Ok(())
+}
+
+/// Run the ROM, checking it against a reference trace from another emulator before each
+/// instruction, and report where they first differ. Anything but a match is an error.
+fn compare_with_reference(options: &Options) -> Result<(), String> {
+    let reference_path = options
+        .reference
+        .as_ref()
+        .ok_or("compare needs a --reference trace.")?;
+    let text =
+        fs::read_to_string(reference_path).map_err(|e| format!("{}: {}", reference_path, e))?;
+    let reference =
+        TraceState::parse_trace(&text).map_err(|e| format!("{}: {}", reference_path, e))?;
+
+    let mut state = Chip8::new(options.rom.platform);
+    state.seed_rng(options.seed);
+    state
+        .load_rom(&options.rom.path)
+        .map_err(|e| format!("{}: {}", options.rom.path, e))?;
+
+    match compare_trace(&mut state, &reference, options.rom.instructions_per_frame) {
+        Ok(comparison @ TraceComparison::Matched(_)) => {
+            println!("{}", comparison);
+            Ok(())
+        }
+        Ok(comparison) => Err(comparison.to_string()),
+        Err(e) => Err(format!("{}\n{}", e, state.dump_state())),
     }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() < 2 {
-        println!("USAGE: {} <rom-file>", args[0]);
+    // A subcommand comes before the usual arguments.
+    let (subcommand, args) = match args.get(1).map(String::as_str) {
+        Some("disasm") => (Some("disasm"), &args[1..]),
+        Some("asm") => (Some("asm"), &args[1..]),
+        Some("compare") => (Some("compare"), &args[1..]),
+        _ => (None, &args[..]),
+    };
+
+    let options = match Options::parse(args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(1);
+        }
+    };
+
+    if let Some(subcommand) = subcommand {
+        let result = match subcommand {
+            "disasm" => disassemble_rom(&options),
+            "asm" => assemble_source(&options),
+            _ => compare_with_reference(&options),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
         return;
     }
 
-    let filename = &args[1];
-
-    let emulator = Emulator::new(filename);
+    let emulator = Emulator::new(&options);
 
     match emulator {
         Ok(mut e) => e.run_forever(),
@@ -1,3 +1,6 @@
+use crate::slots::SaveSlot;
+use sdl2::gfx::primitives::DrawRenderer;
+
 pub struct Screen {
     sdl_canvas: sdl2::render::Canvas<sdl2::video::Window>,
     scale_factor: u32,
@@ -7,7 +10,21 @@ impl Screen {
     const CHIP8_WIDTH: u32 = 64;
     const CHIP8_HEIGHT: u32 = 32;
     const BG_COLOR: sdl2::pixels::Color = sdl2::pixels::Color::RGB(0, 0, 0);
-    const PIXEL_COLOR: sdl2::pixels::Color = sdl2::pixels::Color::RGB(255, 255, 255);
+
+    /// Colour for each combination of XO-CHIP planes a pixel is set on. Index 0 is unused.
+    const PIXEL_COLORS: [sdl2::pixels::Color; 4] = [
+        Self::BG_COLOR,
+        sdl2::pixels::Color::RGB(255, 255, 255), // Plane 1.
+        sdl2::pixels::Color::RGB(170, 170, 170), // Plane 2.
+        sdl2::pixels::Color::RGB(85, 85, 85),    // Both planes.
+    ];
+
+    // Slot picker layout and colours.
+    const PICKER_COLUMNS: u32 = 5;
+    const PICKER_ROWS: u32 = 2;
+    const PICKER_TEXT_COLOR: sdl2::pixels::Color = sdl2::pixels::Color::RGB(255, 255, 255);
+    const PICKER_EMPTY_COLOR: sdl2::pixels::Color = sdl2::pixels::Color::RGB(40, 40, 40);
+    const PICKER_SELECTED_COLOR: sdl2::pixels::Color = sdl2::pixels::Color::RGB(255, 200, 0);
 
     pub fn new(context: &sdl2::Sdl, scale_factor: u32) -> Result<Self, String> {
         let video_subsys = context.video()?;
@@ -33,31 +50,108 @@ impl Screen {
 
     /// Iterate through all pixels in buffer and draw only those that are set active.
     /// The screen is first blanked, then all pixels in buffer are evaluated for being active.
-    /// The remaining pixels are drawn as filled rects, scaled by scale_factor.
-    pub fn draw(&mut self, &buffer: &[bool; 64 * 32]) {
-        let rects: Vec<sdl2::rect::Rect> = buffer
-            .iter()
-            .enumerate()
-            .filter(|(_, &x)| x)
-            .map(|(n, _)| {
-                // Row-major, so we divide and modulo by width to get row and column number.
-                let row = n / Self::CHIP8_WIDTH as usize;
-                let col = n % Self::CHIP8_WIDTH as usize;
-
-                return sdl2::rect::Rect::new(
-                    (col * self.scale_factor as usize) as i32,
-                    (row * self.scale_factor as usize) as i32,
-                    self.scale_factor,
-                    self.scale_factor,
-                );
-            })
-            .collect();
+    /// The remaining pixels are drawn as filled rects, scaled by scale_factor, in the colour for
+    /// the planes they are set on.
+    /// The window keeps its size, so a hires (128x64) buffer is drawn with pixels half as large.
+    pub fn draw(&mut self, buffer: &[u8], (width, _height): (usize, usize)) {
+        let pixel_size = Self::CHIP8_WIDTH * self.scale_factor / width as u32;
 
         self.sdl_canvas.set_draw_color(Self::BG_COLOR);
         self.sdl_canvas.clear();
 
-        self.sdl_canvas.set_draw_color(Self::PIXEL_COLOR);
-        self.sdl_canvas.fill_rects(&rects).unwrap();
+        for planes in 1..Self::PIXEL_COLORS.len() {
+            let rects: Vec<sdl2::rect::Rect> = buffer
+                .iter()
+                .enumerate()
+                .filter(|(_, &x)| x as usize == planes)
+                .map(|(n, _)| {
+                    // Row-major, so we divide and modulo by width to get row and column number.
+                    let row = n / width;
+                    let col = n % width;
+
+                    sdl2::rect::Rect::new(
+                        (col * pixel_size as usize) as i32,
+                        (row * pixel_size as usize) as i32,
+                        pixel_size,
+                        pixel_size,
+                    )
+                })
+                .collect();
+
+            self.sdl_canvas.set_draw_color(Self::PIXEL_COLORS[planes]);
+            self.sdl_canvas.fill_rects(&rects).unwrap();
+        }
+
+        self.sdl_canvas.present();
+    }
+
+    /// Draw the save slots as a grid of thumbnails, each labelled with its number and age, with a
+    /// frame around the selected one. Empty slots are drawn as a dark box.
+    pub fn draw_slot_picker(&mut self, slots: &[Option<SaveSlot>], selected: usize) {
+        let (thumb_width, thumb_height) = SaveSlot::THUMBNAIL_SIZE;
+        let cell_width = Self::CHIP8_WIDTH * self.scale_factor / Self::PICKER_COLUMNS;
+        let cell_height = Self::CHIP8_HEIGHT * self.scale_factor / Self::PICKER_ROWS;
+        let pixel_size = (cell_width * 3 / 4 / thumb_width as u32).max(1);
+        let (width, height) = (
+            thumb_width as u32 * pixel_size,
+            thumb_height as u32 * pixel_size,
+        );
+
+        self.sdl_canvas.set_draw_color(Self::BG_COLOR);
+        self.sdl_canvas.clear();
+
+        for (n, slot) in slots.iter().enumerate() {
+            // Centre the thumbnail in its cell, with room for the label underneath.
+            let x =
+                (n as u32 % Self::PICKER_COLUMNS * cell_width + (cell_width - width) / 2) as i32;
+            let y =
+                (n as u32 / Self::PICKER_COLUMNS * cell_height + (cell_height - height) / 3) as i32;
+            let frame = sdl2::rect::Rect::new(x - 4, y - 4, width + 8, height + 8);
+
+            if n == selected {
+                self.sdl_canvas.set_draw_color(Self::PICKER_SELECTED_COLOR);
+                self.sdl_canvas.fill_rect(frame).unwrap();
+            }
+            self.sdl_canvas.set_draw_color(Self::BG_COLOR);
+            self.sdl_canvas
+                .fill_rect(sdl2::rect::Rect::new(x, y, width, height))
+                .unwrap();
+
+            let label = match slot {
+                Some(slot) => {
+                    for (p, &planes) in slot.thumbnail.iter().enumerate() {
+                        if planes == 0 {
+                            continue;
+                        }
+                        let (row, col) = ((p / thumb_width) as u32, (p % thumb_width) as u32);
+                        self.sdl_canvas
+                            .set_draw_color(Self::PIXEL_COLORS[planes as usize & 3]);
+                        self.sdl_canvas
+                            .fill_rect(sdl2::rect::Rect::new(
+                                x + (col * pixel_size) as i32,
+                                y + (row * pixel_size) as i32,
+                                pixel_size,
+                                pixel_size,
+                            ))
+                            .unwrap();
+                    }
+                    format!("{}: {}", n, slot.age())
+                }
+                None => {
+                    self.sdl_canvas.set_draw_color(Self::PICKER_EMPTY_COLOR);
+                    self.sdl_canvas
+                        .fill_rect(sdl2::rect::Rect::new(x, y, width, height))
+                        .unwrap();
+                    format!("{}: empty", n)
+                }
+            };
+
+            let label_y = y + height as i32 + 12;
+            self.sdl_canvas
+                .string(x as i16, label_y as i16, &label, Self::PICKER_TEXT_COLOR)
+                .unwrap();
+        }
+
         self.sdl_canvas.present();
     }
 }
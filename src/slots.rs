@@ -0,0 +1,149 @@
+use chip8_core::Chip8;
+use std::convert::TryInto;
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A save state in one of the numbered slots, with when it was saved and a thumbnail of the
+/// display at the time so the slot picker can show what's in it.
+///
+/// Slots are stored in files next to the ROM, eg. `roms/TETRIS.3.state`. A slot file is the
+/// magic `C8SL`, the save time in seconds since the Unix epoch (u64, little-endian), the
+/// thumbnail, then the Chip8 snapshot.
+pub struct SaveSlot {
+    pub saved_at: SystemTime,
+    pub thumbnail: Vec<u8>, // 64x32, row-major, in the same pixel format as the graphics buffer.
+    pub snapshot: Vec<u8>,
+}
+
+impl SaveSlot {
+    pub const COUNT: usize = 10;
+    pub const THUMBNAIL_SIZE: (usize, usize) = (64, 32);
+    const MAGIC: &'static [u8; 4] = b"C8SL";
+
+    /// Save the Chip8 as it is now.
+    pub fn new(state: &Chip8) -> Self {
+        Self {
+            saved_at: SystemTime::now(),
+            thumbnail: thumbnail(&state.graphics_buffer, state.display_size()),
+            snapshot: state.snapshot(),
+        }
+    }
+
+    /// The file a slot is stored in for a ROM.
+    pub fn path(rom_path: &str, slot: usize) -> String {
+        format!("{}.{}.state", rom_path, slot)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        Self::decode(&bytes)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.encode()).map_err(|e| e.to_string())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let seconds = self
+            .saved_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(Self::MAGIC);
+        bytes.extend_from_slice(&seconds.to_le_bytes());
+        bytes.extend_from_slice(&self.thumbnail);
+        bytes.extend_from_slice(&self.snapshot);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let (width, height) = Self::THUMBNAIL_SIZE;
+        let header_size = Self::MAGIC.len() + 8;
+
+        if !bytes.starts_with(Self::MAGIC) {
+            return Err("Not a save slot file.".to_string());
+        }
+        if bytes.len() < header_size + width * height {
+            return Err("Save slot file is truncated.".to_string());
+        }
+
+        let seconds = u64::from_le_bytes(bytes[Self::MAGIC.len()..header_size].try_into().unwrap());
+        let (thumbnail, snapshot) = bytes[header_size..].split_at(width * height);
+
+        Ok(Self {
+            saved_at: UNIX_EPOCH + Duration::from_secs(seconds),
+            thumbnail: thumbnail.to_vec(),
+            snapshot: snapshot.to_vec(),
+        })
+    }
+
+    /// How long ago the slot was saved, roughly, eg. "5m ago".
+    pub fn age(&self) -> String {
+        let seconds = self
+            .saved_at
+            .elapsed()
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        match seconds {
+            0..=59 => format!("{}s ago", seconds),
+            60..=3599 => format!("{}m ago", seconds / 60),
+            3600..=86399 => format!("{}h ago", seconds / 3600),
+            _ => format!("{}d ago", seconds / 86400),
+        }
+    }
+}
+
+/// Shrink a display buffer to the thumbnail size. A hires display has each block of pixels
+/// merged, a pixel's planes being all those set anywhere in its block.
+fn thumbnail(buffer: &[u8], (width, height): (usize, usize)) -> Vec<u8> {
+    let (thumb_width, thumb_height) = SaveSlot::THUMBNAIL_SIZE;
+    let (scale_x, scale_y) = (width / thumb_width, height / thumb_height);
+
+    let mut thumbnail = vec![0; thumb_width * thumb_height];
+    for (n, &planes) in buffer.iter().enumerate() {
+        let (row, col) = (n / width / scale_y, n % width / scale_x);
+        thumbnail[row * thumb_width + col] |= planes;
+    }
+    thumbnail
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A hires display is halved, keeping any set pixel.
+    #[test]
+    fn test_thumbnail() {
+        let mut buffer = vec![0; 128 * 64];
+        buffer[0] = 1;
+        buffer[128 + 1] = 2;
+        buffer[127 + 128 * 63] = 1;
+
+        let thumbnail = thumbnail(&buffer, (128, 64));
+        assert_eq!(thumbnail.len(), 64 * 32);
+        assert_eq!(thumbnail[0], 3);
+        assert_eq!(thumbnail[63 + 64 * 31], 1);
+        assert_eq!(thumbnail.iter().filter(|&&p| p > 0).count(), 2);
+    }
+
+    /// A slot decodes to what was encoded, and other files are rejected.
+    #[test]
+    fn test_slot_round_trip() {
+        let slot = SaveSlot {
+            saved_at: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+            thumbnail: vec![1; 64 * 32],
+            snapshot: b"C8SS...".to_vec(),
+        };
+
+        let decoded = SaveSlot::decode(&slot.encode()).unwrap();
+        assert_eq!(decoded.saved_at, slot.saved_at);
+        assert_eq!(decoded.thumbnail, slot.thumbnail);
+        assert_eq!(decoded.snapshot, slot.snapshot);
+
+        assert!(SaveSlot::decode(b"C8SS").is_err());
+        assert!(SaveSlot::decode(&slot.encode()[..100]).is_err());
+    }
+}